@@ -3,6 +3,7 @@ use crate::util::*;
 use crate::vec3::Vec3;
 use std::mem::swap;
 
+#[derive(Debug, Copy, Clone)]
 pub struct AABB {
     pub min: Point3,
     pub max: Point3,
@@ -13,6 +14,30 @@ impl AABB {
         AABB { min, max }
     }
 
+    pub fn zero() -> AABB {
+        AABB::new(Point3::zero(), Point3::zero())
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.max - self.min;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let d = self.max - self.min;
+        if d.x > d.y && d.x > d.z {
+            0
+        } else if d.y > d.z {
+            1
+        } else {
+            2
+        }
+    }
+
     pub fn hit(&self, r: &Ray, t_min_init: f64, t_max_init: f64) -> bool {
         let mut t_min = t_min_init;
         let mut t_max = t_max_init;
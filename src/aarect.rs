@@ -2,7 +2,7 @@ use crate::aabb::AABB;
 use crate::hittable::{HitRecord, Hittable, SharedHittable};
 use crate::material::SharedMaterial;
 use crate::ray::Ray;
-use crate::util::Time;
+use crate::util::{Point3, Time};
 use crate::vec3::Vec3;
 
 pub struct Rect2D {
@@ -27,9 +27,19 @@ impl Missing {
             Self::Z => 2,
         }
     }
+
+    /// The two axes spanning the rectangle.
+    fn axes(&self) -> (usize, usize) {
+        match self {
+            Self::X => (1, 2),
+            Self::Y => (0, 2),
+            Self::Z => (0, 1),
+        }
+    }
 }
 
 impl Rect2D {
+    #[allow(clippy::new_ret_no_self)]
     fn new(
         v0: Vec3,
         v1: Vec3,
@@ -175,4 +185,31 @@ impl Hittable for Rect2D {
         upper[self.missing.idx()] = self.k + 0.0001;
         Some(AABB::new(lower, upper))
     }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn sample_direction(&self, origin: &Point3, (u1, u2): (f64, f64)) -> Vec3 {
+        let (a, b) = self.missing.axes();
+        let mut p = Point3::zero();
+        p[a] = self.v0[a] + u1 * (self.v1[a] - self.v0[a]);
+        p[b] = self.v0[b] + u2 * (self.v1[b] - self.v0[b]);
+        p[self.missing.idx()] = self.k;
+        p - origin
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let rec = match self.hit(&Ray::new(*origin, *direction, 0.0), 0.001, f64::INFINITY) {
+            Some(rec) => rec,
+            None => return 0.0,
+        };
+
+        // Convert the uniform density over the area to one over solid angle
+        let (a, b) = self.missing.axes();
+        let area = (self.v1[a] - self.v0[a]) * (self.v1[b] - self.v0[b]);
+        let distance_squared = rec.t * rec.t * direction.mag_squared();
+        let cosine = direction[self.missing.idx()].abs() / direction.mag();
+        distance_squared / (cosine * area)
+    }
 }
@@ -1,74 +1,447 @@
-
-use crate::aabb::AABB;
-use crate::hittable::{Hittable, SharedHittable, HitRecord};
+use crate::aabb::{surrounding_box, AABB};
+use crate::hittable::{HitRecord, Hittable, SharedHittable};
+use crate::ray::Ray;
 use crate::util::*;
-use rand::prelude::*;
+use std::cmp::Ordering;
+
+const SAH_BINS: usize = 12;
+const SAH_MAX_LEAF_SIZE: usize = 4;
+// Cost of visiting an interior node relative to intersecting a single primitive
+const SAH_TRAVERSAL_COST: f64 = 0.125;
 
+/// Strategy used to split a set of primitives while building a BVH.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BvhSplit {
+    /// Sort along the longest axis and split at the median.
+    Median,
+    /// Bin centroids along the longest axis and pick the split with the lowest surface area
+    /// heuristic cost.
+    Sah,
+}
 
 pub struct BvhNode {
     left: SharedHittable,
     right: SharedHittable,
-    bbox: AABB;
+    bbox: AABB,
+}
+
+fn bounding_box_or_zero(obj: &SharedHittable, t0: Time, t1: Time) -> AABB {
+    obj.bounding_box(t0, t1).unwrap_or_else(|| {
+        eprintln!("No bounding box in BvhNode construction");
+        AABB::zero()
+    })
+}
+
+fn box_compare(a: &AABB, b: &AABB, axis: usize) -> Ordering {
+    a.min[axis]
+        .partial_cmp(&b.min[axis])
+        .unwrap_or(Ordering::Equal)
+}
+
+fn enclosing_box<T>(prims: &[(AABB, T)]) -> AABB {
+    prims
+        .iter()
+        .map(|(bbox, _)| *bbox)
+        .reduce(|acc, bbox| surrounding_box(&acc, &bbox))
+        .unwrap_or_else(AABB::zero)
+}
+
+fn median_split<T>(prims: &mut [(AABB, T)], bbox: &AABB) -> (usize, usize) {
+    let axis = bbox.longest_axis();
+    prims.sort_by(|(a, _), (b, _)| box_compare(a, b, axis));
+    (prims.len() / 2, axis)
 }
 
-fn box_compare(a: &SharedHittable, b: &SharedHittable, axis: usize) -> Ordering {
-    let a_hit = a.bounding_box(0.0, 0.0);
-    let b_hit = b.bounding_box(0.0, 0.0);
+fn sah_split<T>(prims: &mut [(AABB, T)], bbox: &AABB) -> Option<(usize, usize)> {
+    let n = prims.len();
+    let axis = bbox.longest_axis();
+
+    let (cmin, cmax) = prims
+        .iter()
+        .map(|(b, _)| b.centroid()[axis])
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), c| {
+            (lo.min(c), hi.max(c))
+        });
+
+    // All centroids coincide along the axis, so binning cannot separate them
+    if cmax - cmin <= 0.0 {
+        return Some(median_split(prims, bbox));
+    }
+
+    let bin_of = |b: &AABB| {
+        let rel = (b.centroid()[axis] - cmin) / (cmax - cmin);
+        ((rel * SAH_BINS as f64) as usize).min(SAH_BINS - 1)
+    };
+
+    let mut counts = [0usize; SAH_BINS];
+    let mut bounds: [Option<AABB>; SAH_BINS] = [None; SAH_BINS];
+    for (b, _) in prims.iter() {
+        let i = bin_of(b);
+        counts[i] += 1;
+        bounds[i] = Some(bounds[i].map_or(*b, |acc| surrounding_box(&acc, b)));
+    }
+
+    // Sweep from the right to get the area and count of everything past each split
+    let mut right_area = [0.0; SAH_BINS];
+    let mut right_count = [0usize; SAH_BINS];
+    let mut acc: Option<AABB> = None;
+    let mut count = 0;
+    for i in (1..SAH_BINS).rev() {
+        if let Some(b) = bounds[i] {
+            acc = Some(acc.map_or(b, |a| surrounding_box(&a, &b)));
+        }
+        count += counts[i];
+        right_area[i] = acc.map_or(0.0, |a| a.surface_area());
+        right_count[i] = count;
+    }
+
+    // Sweep from the left, splitting between bin `i - 1` and bin `i`
+    let mut best: Option<(usize, f64)> = None;
+    let mut acc: Option<AABB> = None;
+    let mut count = 0;
+    for i in 1..SAH_BINS {
+        if let Some(b) = bounds[i - 1] {
+            acc = Some(acc.map_or(b, |a| surrounding_box(&a, &b)));
+        }
+        count += counts[i - 1];
+        if count == 0 || right_count[i] == 0 {
+            continue;
+        }
+
+        let left_area = acc.map_or(0.0, |a| a.surface_area());
+        let cost = left_area * count as f64 + right_area[i] * right_count[i] as f64;
+        if best.is_none_or(|(_, best_cost)| cost < best_cost) {
+            best = Some((i, cost));
+        }
+    }
 
-    if a_hit.is_none() || b_hit.is_none() {
-        eprintln!("No bounding box in BvhNode compare")
+    let (split_bin, cost) = best?;
+    let cost = SAH_TRAVERSAL_COST + cost / bbox.surface_area().max(f64::MIN_POSITIVE);
+    if n <= SAH_MAX_LEAF_SIZE && cost >= n as f64 {
+        return None;
     }
 
-    a_hit.unwrap_or(AABB::zero()).min[axis].cmp(b_hit.unwrap_or(AABB::zero()).min[axis])
+    // Partition in place so everything left of the split bin comes first
+    let mut mid = 0;
+    for i in 0..n {
+        if bin_of(&prims[i].0) < split_bin {
+            prims.swap(i, mid);
+            mid += 1;
+        }
+    }
+    Some((mid, axis))
 }
 
+/// Reorders `prims` and returns the index to split them at along with the split axis, or `None`
+/// if they should stay together in a single leaf.
+fn partition<T>(prims: &mut [(AABB, T)], bbox: &AABB, split: BvhSplit) -> Option<(usize, usize)> {
+    if prims.len() <= 1 {
+        return None;
+    }
+
+    match split {
+        BvhSplit::Median => Some(median_split(prims, bbox)),
+        BvhSplit::Sah => sah_split(prims, bbox),
+    }
+}
 
 impl BvhNode {
-    pub fn new(left: SharedHittable, right: SharedHittable, bbox: AABB) -> BvhNode {
-        BvhNode { left, right, bbox }
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(left: SharedHittable, right: SharedHittable, bbox: AABB) -> SharedHittable {
+        Box::new(BvhNode { left, right, bbox })
+    }
+
+    /// Builds a bounding volume hierarchy that takes ownership of `objects` using the given
+    /// split strategy.
+    pub fn from_objects(
+        t0: Time,
+        t1: Time,
+        objects: Vec<SharedHittable>,
+        split: BvhSplit,
+    ) -> SharedHittable {
+        let prims = objects
+            .into_iter()
+            .map(|obj| (bounding_box_or_zero(&obj, t0, t1), obj))
+            .collect();
+        Self::build_node(prims, split)
+    }
+
+    fn build_node(mut prims: Vec<(AABB, SharedHittable)>, split: BvhSplit) -> SharedHittable {
+        let bbox = enclosing_box(&prims);
+
+        match partition(&mut prims, &bbox, split) {
+            Some((mid, _)) => {
+                let right_prims = prims.split_off(mid);
+                let left = Self::build_node(prims, split);
+                let right = Self::build_node(right_prims, split);
+                BvhNode::new(left, right, bbox)
+            }
+            None if prims.len() == 1 => prims.pop().unwrap().1,
+            None => Box::new(prims.into_iter().map(|(_, obj)| obj).collect::<Vec<_>>()),
+        }
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        if !self.bbox.hit(r, t_min, t_max) {
+            return None;
+        }
+
+        let left_hit = self.left.hit(r, t_min, t_max);
+        let right_hit = self
+            .right
+            .hit(r, t_min, left_hit.as_ref().map_or(t_max, |rec| rec.t));
+
+        right_hit.or(left_hit)
     }
 
-    pub fn from_objects(t0: Time, t1: Time, objects: &[SharedHittable]) -> BvhNode {
-        let axis = thread_rng().gen_range(0, 3);
+    fn bounding_box(&self, _t0: Time, _t1: Time) -> Option<AABB> {
+        Some(self.bbox)
+    }
+}
 
-        let comparator = |a, b| box_compare(a, b, axis);
+// Bounds the traversal stack; deeper subtrees are collapsed into a single leaf
+const LINEAR_BVH_MAX_DEPTH: usize = 64;
 
-        let (left, right) = if objects.len() == 1 {
-            (objects[0], objects[0])
-        } else {
-            let mut v = Vec::clone_from_slice(objects);
-            v.sort_by(comparator);
-            let mid = objects.len() / 2;
+struct LinearNode {
+    bbox: AABB,
+    // Index of the first primitive for leaves, index of the second child for interior nodes
+    offset: usize,
+    // Number of primitives in a leaf, zero for interior nodes
+    count: usize,
+    axis: usize,
+}
+
+/// A bounding volume hierarchy flattened into a contiguous array of nodes in depth-first order.
+///
+/// The first child of an interior node immediately follows it, and leaves refer to a range of
+/// `primitives`, which are reordered to be contiguous per leaf.
+pub struct LinearBvh {
+    nodes: Vec<LinearNode>,
+    primitives: Vec<SharedHittable>,
+}
 
-            (objects[..mid], objects[mid..])
+impl LinearBvh {
+    pub fn from_objects(
+        t0: Time,
+        t1: Time,
+        objects: Vec<SharedHittable>,
+        split: BvhSplit,
+    ) -> SharedHittable {
+        let mut prims: Vec<(AABB, usize)> = objects
+            .iter()
+            .enumerate()
+            .map(|(i, obj)| (bounding_box_or_zero(obj, t0, t1), i))
+            .collect();
+
+        let mut nodes = Vec::with_capacity(2 * prims.len());
+        if !prims.is_empty() {
+            Self::build_node(&mut nodes, &mut prims, 0, split, 0);
         }
 
-        let left_hit = left.bounding_box(t0, t1);
-        let right_hit = right.bounding_box(t0, t1);
+        // Move the objects into the order the leaves expect
+        let mut objects: Vec<Option<SharedHittable>> = objects.into_iter().map(Some).collect();
+        let primitives = prims
+            .iter()
+            .map(|(_, i)| objects[*i].take().unwrap())
+            .collect();
 
-        if left_hit.is_none() || right_hit.is_none() {
-            eprintln!("No bounding box in BvhNode::from_objects")
+        Box::new(LinearBvh { nodes, primitives })
+    }
+
+    fn build_node(
+        nodes: &mut Vec<LinearNode>,
+        prims: &mut [(AABB, usize)],
+        first: usize,
+        split: BvhSplit,
+        depth: usize,
+    ) {
+        let bbox = enclosing_box(prims);
+        let idx = nodes.len();
+        nodes.push(LinearNode {
+            bbox,
+            offset: first,
+            count: prims.len(),
+            axis: 0,
+        });
+
+        if depth + 1 >= LINEAR_BVH_MAX_DEPTH {
+            return;
         }
 
-        let bbox = surrounding_box(left_hit.unwrap_or(AABB::zero()), right_hit.unwrap_or(AABB::zero()));
+        if let Some((mid, axis)) = partition(prims, &bbox, split) {
+            let (left, right) = prims.split_at_mut(mid);
+            Self::build_node(nodes, left, first, split, depth + 1);
+            let second = nodes.len();
+            Self::build_node(nodes, right, first + mid, split, depth + 1);
 
-        BvhNode::new(left, right, bbox)
+            nodes[idx].offset = second;
+            nodes[idx].count = 0;
+            nodes[idx].axis = axis;
+        }
     }
 }
 
-impl Hittable for BvhNode {
+impl Hittable for LinearBvh {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        if self.bbox.hit(r, t_min, t_max) {
-            let left_hit = left.hit(r, t_min, t_max);
-            let right_hit = right.hit(r, t_min, left_hit.map_or(t_max, |r| r.t));
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let dir_is_neg = [
+            r.direction.x < 0.0,
+            r.direction.y < 0.0,
+            r.direction.z < 0.0,
+        ];
+        let mut stack = [0usize; LINEAR_BVH_MAX_DEPTH];
+        let mut stack_len = 0;
+        let mut current = 0;
+
+        let mut closest_so_far = t_max;
+        let mut rec = None;
+
+        loop {
+            let node = &self.nodes[current];
+
+            if node.bbox.hit(r, t_min, closest_so_far) {
+                if node.count > 0 {
+                    for obj in &self.primitives[node.offset..node.offset + node.count] {
+                        if let Some(obj_rec) = obj.hit(r, t_min, closest_so_far) {
+                            closest_so_far = obj_rec.t;
+                            rec = Some(obj_rec);
+                        }
+                    }
+                } else {
+                    // Visit the child nearer along the split axis first
+                    let (near, far) = if dir_is_neg[node.axis] {
+                        (node.offset, current + 1)
+                    } else {
+                        (current + 1, node.offset)
+                    };
+                    stack[stack_len] = far;
+                    stack_len += 1;
+                    current = near;
+                    continue;
+                }
+            }
+
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            current = stack[stack_len];
+        }
+
+        rec
+    }
+
+    fn bounding_box(&self, _t0: Time, _t1: Time) -> Option<AABB> {
+        self.nodes.first().map(|node| node.bbox)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColor;
+    use rand::SeedableRng;
+
+    fn spheres(params: &[(Point3, f64)]) -> Vec<SharedHittable> {
+        let mat = Lambertian::new(SolidColor::new(Color::one()));
+        params
+            .iter()
+            .map(|(center, radius)| Sphere::new(*center, *radius, mat.clone()))
+            .collect()
+    }
+
+    fn check_matches_linear(
+        split: BvhSplit,
+        build: fn(Time, Time, Vec<SharedHittable>, BvhSplit) -> SharedHittable,
+    ) {
+        let mut rng = Rng::seed_from_u64(1);
+        let params: Vec<_> = (0..200)
+            .map(|_| {
+                (
+                    Point3::random_range(-10.0, 10.0, &mut rng),
+                    rand_range(0.1, 1.0, &mut rng),
+                )
+            })
+            .collect();
+        let linear = spheres(&params);
+        let bvh = build(0.0, 1.0, spheres(&params), split);
+
+        for _ in 0..500 {
+            let origin = Point3::random_range(-20.0, 20.0, &mut rng);
+            let target = Point3::random_range(-10.0, 10.0, &mut rng);
+            let r = Ray::new(origin, target - origin, 0.0);
 
-            right_hit.or(left_hit)
-        } else {
-            None
+            let expected = linear.hit(&r, 0.001, f64::INFINITY).map(|rec| rec.t);
+            let actual = bvh.hit(&r, 0.001, f64::INFINITY).map(|rec| rec.t);
+            match (expected, actual) {
+                (Some(e), Some(a)) => assert!((e - a).abs() < 1e-9),
+                (None, None) => {}
+                _ => panic!(
+                    "BVH and linear hit disagree: {:?} vs {:?}",
+                    expected, actual
+                ),
+            }
         }
     }
 
-    fn bounding_box(&self, t0: Time, t1: Time) -> Option<AABB> {
-        Some(self.box)
+    #[test]
+    fn test_nearest_hit_and_bounds() {
+        // Overlapping spheres along the x axis, added out of order
+        let bvh = BvhNode::from_objects(
+            0.0,
+            1.0,
+            spheres(&[
+                (Point3::new(4.0, 0.0, 0.0), 1.0),
+                (Point3::new(0.0, 0.0, 0.0), 1.5),
+                (Point3::new(2.0, 0.0, 0.0), 1.0),
+            ]),
+            BvhSplit::Median,
+        );
+
+        let r = Ray::new(
+            Point3::new(10.0, 0.0, 0.0),
+            Point3::new(-1.0, 0.0, 0.0),
+            0.0,
+        );
+        let rec = bvh.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 5.0).abs() < 1e-9);
+        let rec = bvh.hit(&r, 5.5, f64::INFINITY).unwrap();
+        assert!((rec.t - 7.0).abs() < 1e-9);
+
+        let bbox = bvh.bounding_box(0.0, 1.0).unwrap();
+        assert!((bbox.min - Point3::new(-1.5, -1.5, -1.5)).mag() < 1e-9);
+        assert!((bbox.max - Point3::new(5.0, 1.5, 1.5)).mag() < 1e-9);
+    }
+
+    #[test]
+    fn test_median_matches_linear() {
+        check_matches_linear(BvhSplit::Median, BvhNode::from_objects);
+    }
+
+    #[test]
+    fn test_sah_matches_linear() {
+        check_matches_linear(BvhSplit::Sah, BvhNode::from_objects);
+    }
+
+    #[test]
+    fn test_linear_bvh_matches_linear() {
+        check_matches_linear(BvhSplit::Median, LinearBvh::from_objects);
+        check_matches_linear(BvhSplit::Sah, LinearBvh::from_objects);
+    }
+
+    #[test]
+    fn test_empty() {
+        let r = Ray::new(Point3::zero(), Point3::one(), 0.0);
+        let bvh = BvhNode::from_objects(0.0, 1.0, Vec::new(), BvhSplit::Sah);
+        assert!(bvh.hit(&r, 0.0, f64::INFINITY).is_none());
+        let bvh = LinearBvh::from_objects(0.0, 1.0, Vec::new(), BvhSplit::Sah);
+        assert!(bvh.hit(&r, 0.0, f64::INFINITY).is_none());
     }
 }
@@ -1,5 +1,6 @@
 use crate::ray::Ray;
-use crate::util::{rand_range, Point3, Time};
+use crate::sampler::Sampler;
+use crate::util::{Point3, Time};
 use crate::vec3::*;
 
 #[derive(Debug, Copy, Clone)]
@@ -8,7 +9,6 @@ pub struct Camera {
     lower_left: Point3,
     horizontal: Vec3,
     vertical: Vec3,
-    w: Vec3,
     u: Vec3,
     v: Vec3,
     lens_radius: f64,
@@ -17,6 +17,7 @@ pub struct Camera {
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         look_from: &Point3,
         look_at: &Point3,
@@ -34,7 +35,7 @@ impl Camera {
         let viewport_width = aspect_ratio * viewport_height;
 
         let w = (look_from - look_at).normalized();
-        let u = cross(&vup, &w).normalized();
+        let u = cross(vup, &w).normalized();
         let v = cross(&w, &u);
 
         let origin = *look_from;
@@ -48,7 +49,6 @@ impl Camera {
             lower_left,
             horizontal,
             vertical,
-            w,
             u,
             v,
             lens_radius,
@@ -57,13 +57,14 @@ impl Camera {
         }
     }
 
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = self.lens_radius * random_in_unit_disk();
+    pub fn get_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Ray {
+        let rd = self.lens_radius * sample_in_unit_disk(sampler.get_2d());
         let offset = self.u * rd.x + self.v * rd.y;
-        Ray::new(
+        Ray::sampled(
             self.origin + offset,
             self.lower_left + s * self.horizontal + t * self.vertical - self.origin - offset,
-            rand_range(self.time0, self.time1),
+            self.time0 + (self.time1 - self.time0) * sampler.get_1d(),
+            sampler.get_1d(),
         )
     }
 }
@@ -0,0 +1,317 @@
+use crate::integrator::IntegratorType;
+use crate::render::{Accumulator, RenderSettings};
+use crate::sampler::SamplerType;
+use crate::util::Color;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"RTWCKPT6";
+
+/// The longest scene label a checkpoint may hold, so a corrupt header can't demand a huge
+/// allocation.
+const MAX_SCENE_LABEL: usize = 64 * 1024;
+
+/// The state of an interrupted progressive render: the accumulated radiance and sample
+/// counts, along with the settings needed to continue it.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    /// Identifies the scene and camera the samples were taken of
+    pub scene: String,
+    pub max_depth: i32,
+    pub seed: u64,
+    /// The sample count the render was started with, which the stratified sampler divides
+    /// each pixel into
+    pub samples_per_pixel: i32,
+    pub roulette_depth: i32,
+    pub light_sampling: bool,
+    pub ao_distance: f64,
+    pub sampler: SamplerType,
+    pub integrator: IntegratorType,
+    pub accumulator: Accumulator,
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+const SAMPLERS: [SamplerType; 4] = [
+    SamplerType::Independent,
+    SamplerType::Stratified,
+    SamplerType::Halton,
+    SamplerType::Sobol,
+];
+
+const INTEGRATORS: [IntegratorType; 7] = [
+    IntegratorType::Path,
+    IntegratorType::AmbientOcclusion,
+    IntegratorType::Normals,
+    IntegratorType::Albedo,
+    IntegratorType::Uv,
+    IntegratorType::Depth,
+    IntegratorType::Position,
+];
+
+fn read_array<const N: usize>(r: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    Ok(u32::from_le_bytes(read_array(r)?))
+}
+
+fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+    Ok(u64::from_le_bytes(read_array(r)?))
+}
+
+fn read_f64(r: &mut impl Read) -> io::Result<f64> {
+    Ok(f64::from_le_bytes(read_array(r)?))
+}
+
+impl Checkpoint {
+    /// Checks that the samples were taken of `scene` with `settings`, so that more samples can
+    /// be added to them.
+    pub fn check(&self, scene: &str, settings: &RenderSettings) -> Result<(), String> {
+        let acc = &self.accumulator;
+        let mismatch = if self.scene != scene {
+            Some("scene or camera")
+        } else if (acc.width as i32, acc.height as i32)
+            != (settings.image_width, settings.image_height)
+        {
+            Some("image size")
+        } else if self.max_depth != settings.max_depth {
+            Some("maximum depth")
+        } else if self.roulette_depth != settings.roulette_depth {
+            Some("Russian roulette depth")
+        } else if self.light_sampling != settings.light_sampling {
+            Some("light sampling setting")
+        } else if self.ao_distance != settings.ao_distance {
+            Some("ambient occlusion distance")
+        } else if self.seed != settings.seed {
+            Some("seed")
+        } else if self.sampler != settings.sampler {
+            Some("sampler")
+        } else if self.sampler == SamplerType::Stratified
+            && self.samples_per_pixel != settings.samples_per_pixel
+        {
+            Some("sample count, which the stratified sampler can't change")
+        } else if self.integrator != settings.integrator {
+            Some("integrator")
+        } else {
+            None
+        };
+
+        match mismatch {
+            Some(what) => Err(format!("checkpoint was rendered with a different {}", what)),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes a checkpoint of samples taken of `scene` with `settings` to `path`. The file is
+    /// replaced atomically, so an interrupted write leaves the previous checkpoint intact.
+    pub fn save(
+        path: impl AsRef<Path>,
+        scene: &str,
+        settings: &RenderSettings,
+        accumulator: &Accumulator,
+    ) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+
+        let mut w = BufWriter::new(File::create(&tmp)?);
+        w.write_all(MAGIC)?;
+        w.write_all(&(scene.len() as u32).to_le_bytes())?;
+        w.write_all(scene.as_bytes())?;
+        w.write_all(&settings.max_depth.to_le_bytes())?;
+        w.write_all(&settings.seed.to_le_bytes())?;
+        w.write_all(&settings.samples_per_pixel.to_le_bytes())?;
+        w.write_all(&settings.roulette_depth.to_le_bytes())?;
+        w.write_all(&[settings.light_sampling as u8])?;
+        w.write_all(&settings.ao_distance.to_le_bytes())?;
+        let sampler = SAMPLERS
+            .iter()
+            .position(|&s| s == settings.sampler)
+            .unwrap();
+        let integrator = INTEGRATORS
+            .iter()
+            .position(|&i| i == settings.integrator)
+            .unwrap();
+        w.write_all(&[sampler as u8, integrator as u8])?;
+        w.write_all(&accumulator.width.to_le_bytes())?;
+        w.write_all(&accumulator.height.to_le_bytes())?;
+        for pixel in &accumulator.pixels {
+            let (x, y, z) = pixel.sum.as_tuple();
+            for c in [x, y, z, pixel.sum_sq] {
+                w.write_all(&c.to_le_bytes())?;
+            }
+            w.write_all(&pixel.samples.to_le_bytes())?;
+        }
+        w.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+
+        fs::rename(&tmp, path)
+    }
+
+    /// Reads a checkpoint of an image the size `settings` render, rejecting any other size
+    /// before reading the pixels.
+    pub fn load(path: impl AsRef<Path>, settings: &RenderSettings) -> io::Result<Checkpoint> {
+        let mut r = BufReader::new(File::open(path)?);
+
+        if &read_array::<8>(&mut r)? != MAGIC {
+            return Err(invalid_data("not a checkpoint file".to_string()));
+        }
+        let scene_len = read_u32(&mut r)? as usize;
+        if scene_len > MAX_SCENE_LABEL {
+            return Err(invalid_data(format!(
+                "scene label of {} bytes is too long",
+                scene_len
+            )));
+        }
+        let mut scene = vec![0; scene_len];
+        r.read_exact(&mut scene)?;
+        let scene = String::from_utf8(scene).map_err(|err| invalid_data(err.to_string()))?;
+        let max_depth = i32::from_le_bytes(read_array(&mut r)?);
+        let seed = read_u64(&mut r)?;
+        let samples_per_pixel = i32::from_le_bytes(read_array(&mut r)?);
+        let roulette_depth = i32::from_le_bytes(read_array(&mut r)?);
+        let light_sampling = match read_array::<1>(&mut r)? {
+            [0] => false,
+            [1] => true,
+            [b] => return Err(invalid_data(format!("invalid light sampling flag {}", b))),
+        };
+        let ao_distance = read_f64(&mut r)?;
+        let [sampler, integrator] = read_array(&mut r)?;
+        let sampler = *SAMPLERS
+            .get(sampler as usize)
+            .ok_or_else(|| invalid_data(format!("unknown sampler {}", sampler)))?;
+        let integrator = *INTEGRATORS
+            .get(integrator as usize)
+            .ok_or_else(|| invalid_data(format!("unknown integrator {}", integrator)))?;
+        let width = read_u32(&mut r)?;
+        let height = read_u32(&mut r)?;
+        if (width as i64, height as i64)
+            != (settings.image_width as i64, settings.image_height as i64)
+        {
+            return Err(invalid_data(format!(
+                "checkpoint is {}x{} pixels, expected {}x{}",
+                width, height, settings.image_width, settings.image_height
+            )));
+        }
+
+        let mut accumulator = Accumulator::new(width, height);
+        for pixel in &mut accumulator.pixels {
+            pixel.sum = Color::new(read_f64(&mut r)?, read_f64(&mut r)?, read_f64(&mut r)?);
+            pixel.sum_sq = read_f64(&mut r)?;
+            pixel.samples = read_u32(&mut r)?;
+        }
+        if r.read(&mut [0])? != 0 {
+            return Err(invalid_data("trailing data after checkpoint".to_string()));
+        }
+
+        Ok(Checkpoint {
+            scene,
+            max_depth,
+            seed,
+            samples_per_pixel,
+            roulette_depth,
+            light_sampling,
+            ao_distance,
+            sampler,
+            integrator,
+            accumulator,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_save_load() {
+        let mut accumulator = Accumulator::new(3, 2);
+        accumulator.pixels[4].add(Color::new(1.5, -0.0, f64::MAX));
+        accumulator.pixels[4].add(Color::new(0.5, 0.25, 1.0));
+        let settings = RenderSettings {
+            image_width: 3,
+            image_height: 2,
+            seed: u64::MAX,
+            sampler: SamplerType::Halton,
+            integrator: IntegratorType::Uv,
+            ..RenderSettings::default()
+        };
+
+        let path = env::temp_dir().join(format!("checkpoint-test-{}.ckpt", std::process::id()));
+        Checkpoint::save(&path, "scene 6", &settings, &accumulator).unwrap();
+        let loaded = Checkpoint::load(&path, &settings).unwrap();
+        let larger = RenderSettings {
+            image_width: 30000,
+            ..settings
+        };
+        let err = Checkpoint::load(&path, &larger).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut huge_label = MAGIC.to_vec();
+        huge_label.extend_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&path, huge_label).unwrap();
+        let err = Checkpoint::load(&path, &settings).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        assert_eq!(loaded.scene, "scene 6");
+        assert_eq!((loaded.max_depth, loaded.seed), (50, u64::MAX));
+        assert_eq!(loaded.sampler, SamplerType::Halton);
+        assert_eq!(loaded.integrator, IntegratorType::Uv);
+        assert!(loaded.check("scene 6", &settings).is_ok());
+        assert!(loaded.check("scene 7", &settings).is_err());
+        for changed in [
+            RenderSettings {
+                roulette_depth: settings.roulette_depth + 1,
+                ..settings
+            },
+            RenderSettings {
+                light_sampling: !settings.light_sampling,
+                ..settings
+            },
+            RenderSettings {
+                ao_distance: 1.0,
+                ..settings
+            },
+        ] {
+            assert!(loaded.check("scene 6", &changed).is_err());
+        }
+        let more_samples = RenderSettings {
+            samples_per_pixel: settings.samples_per_pixel * 2,
+            ..settings
+        };
+        assert!(loaded.check("scene 6", &more_samples).is_ok());
+        let stratified = Checkpoint {
+            sampler: SamplerType::Stratified,
+            ..loaded.clone()
+        };
+        let stratified_settings = RenderSettings {
+            sampler: SamplerType::Stratified,
+            ..settings
+        };
+        assert!(stratified.check("scene 6", &stratified_settings).is_ok());
+        assert!(stratified
+            .check(
+                "scene 6",
+                &RenderSettings {
+                    sampler: SamplerType::Stratified,
+                    ..more_samples
+                }
+            )
+            .is_err());
+        let acc = &loaded.accumulator;
+        assert_eq!((acc.width, acc.height), (3, 2));
+        for (a, b) in acc.pixels.iter().zip(&accumulator.pixels) {
+            assert_eq!(a.sum.as_tuple(), b.sum.as_tuple());
+            assert_eq!((a.sum_sq, a.samples), (b.sum_sq, b.samples));
+        }
+    }
+}
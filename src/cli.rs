@@ -0,0 +1,550 @@
+use ray_tracing_weekend::bvh::BvhSplit;
+use ray_tracing_weekend::integrator::IntegratorType;
+use ray_tracing_weekend::output::OutputFormat;
+use ray_tracing_weekend::sampler::SamplerType;
+use ray_tracing_weekend::scenes::SCENE_COUNT;
+use ray_tracing_weekend::tile::TileOrder;
+use ray_tracing_weekend::util::Point3;
+use ray_tracing_weekend::vec3::{cross, Vec3};
+use ray_tracing_weekend::{RenderSettings, Scene};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+pub const USAGE: &str = "\
+Usage: ray-tracing-weekend [OPTIONS]
+
+Renders a scene and writes the image as ASCII PPM to stdout, or to --output.
+
+Scene:
+  -s, --scene <N>            Built-in scene to render, 1-10 [default: 1]
+      --scene-file <PATH>    Load the scene from a TOML scene description file
+
+Image:
+  -w, --width <PIXELS>       Image width
+  -H, --height <PIXELS>      Image height; with --width this sets the aspect ratio
+      --spp <N>              Samples per pixel, the most a pixel gets with --adaptive
+  -d, --max-depth <N>        Maximum number of ray bounces, 0 for no limit
+      --roulette-depth <N>   Bounces before Russian roulette may end a path [default: 3]
+  -o, --output <PATH>        Write a .png, .jpg, .ppm or .hdr file instead of stdout
+      --integrator <NAME>    What to render: path (the image), ao (ambient occlusion), or the
+                             normals, albedo, uv, depth or position of the first hit
+                             [default: path]
+      --ao-distance <D>      How far away geometry occludes with --integrator ao
+                             [default: unlimited]
+
+Camera:
+      --look-from <X,Y,Z>    Camera position
+      --look-at <X,Y,Z>      Point the camera looks at
+      --vup <X,Y,Z>          Camera up direction
+      --vfov <DEGREES>       Vertical field of view
+      --aperture <A>         Lens aperture, 0 for a pinhole camera
+      --focus-dist <D>       Focus distance [default: distance to --look-at when moved]
+
+Adaptive sampling:
+      --adaptive <ERROR>     Stop sampling pixels once their estimated error is below ERROR,
+                             relative to the gamma-corrected range, e.g. 0.01
+      --min-spp <N>          Samples every pixel gets before it may stop [default: 16]
+      --samples-image <PATH> Also write an image of the samples taken per pixel
+
+Progressive rendering:
+      --pass-spp <N>         Samples per pixel added by each pass [default: 16]
+      --checkpoint <PATH>    Periodically save the samples taken so far to a checkpoint file
+      --checkpoint-interval <SECONDS>
+                             Time between checkpoints [default: 60]
+      --resume <PATH>        Continue rendering from a checkpoint up to --spp samples, saving
+                             further checkpoints to the same file unless --checkpoint is given
+
+Execution:
+  -j, --threads <N>          Number of render threads [default: all cores]
+      --seed <N>             Seed for scene generation and sampling [default: 0]
+      --sampler <SAMPLER>    independent, stratified, halton or sobol [default: sobol]
+      --no-light-sampling    Only find lights by bouncing into them, not by sampling them
+      --tile-size <PIXELS>   Size of the square tiles handed to threads [default: 16]
+      --tile-order <ORDER>   Order tiles are rendered in, spiral or scanline [default: spiral]
+      --bvh <median|sah>     BVH split heuristic [default: sah]
+      --bvh-tree             Use the pointer-based BVH instead of the flattened one
+
+  -h, --help                 Print this help
+";
+
+#[derive(Debug)]
+pub struct ArgError(String);
+
+impl fmt::Display for ArgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Command-line overrides of the camera settings of a scene.
+#[derive(Debug, Default)]
+pub struct CameraArgs {
+    pub look_from: Option<Point3>,
+    pub look_at: Option<Point3>,
+    pub vup: Option<Vec3>,
+    pub vfov: Option<f64>,
+    pub aperture: Option<f64>,
+    pub focus_dist: Option<f64>,
+}
+
+#[derive(Debug)]
+pub struct ProgramArgs {
+    pub help: bool,
+    pub scene: i32,
+    pub scene_file: Option<PathBuf>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub samples_per_pixel: Option<i32>,
+    pub max_depth: Option<i32>,
+    pub roulette_depth: i32,
+    pub output: Option<(PathBuf, OutputFormat)>,
+    pub camera: CameraArgs,
+    pub threads: Option<usize>,
+    pub seed: u64,
+    pub sampler: SamplerType,
+    pub light_sampling: bool,
+    pub integrator: IntegratorType,
+    pub ao_distance: f64,
+    pub tile_size: u32,
+    pub tile_order: TileOrder,
+    pub pass_samples: i32,
+    pub adaptive_threshold: f64,
+    pub min_samples: i32,
+    pub samples_image: Option<(PathBuf, OutputFormat)>,
+    pub checkpoint: Option<PathBuf>,
+    pub checkpoint_interval: Duration,
+    pub resume: Option<PathBuf>,
+    pub bvh_split: BvhSplit,
+    pub bvh_tree: bool,
+}
+
+impl Default for ProgramArgs {
+    fn default() -> Self {
+        ProgramArgs {
+            help: false,
+            scene: 1,
+            scene_file: None,
+            width: None,
+            height: None,
+            samples_per_pixel: None,
+            max_depth: None,
+            roulette_depth: 3,
+            output: None,
+            camera: CameraArgs::default(),
+            threads: None,
+            seed: 0,
+            sampler: SamplerType::Sobol,
+            light_sampling: true,
+            integrator: IntegratorType::Path,
+            ao_distance: f64::INFINITY,
+            tile_size: 16,
+            tile_order: TileOrder::Spiral,
+            pass_samples: 16,
+            adaptive_threshold: 0.0,
+            min_samples: 16,
+            samples_image: None,
+            checkpoint: None,
+            checkpoint_interval: Duration::from_secs(60),
+            resume: None,
+            bvh_split: BvhSplit::Sah,
+            bvh_tree: false,
+        }
+    }
+}
+
+fn parse<T: FromStr>(option: &str, value: &str) -> Result<T, ArgError> {
+    value
+        .parse()
+        .map_err(|_| ArgError(format!("invalid value '{}' for {}", value, option)))
+}
+
+fn positive<T: FromStr + PartialOrd + Default>(option: &str, value: &str) -> Result<T, ArgError> {
+    let n: T = parse(option, value)?;
+    if n > T::default() {
+        Ok(n)
+    } else {
+        Err(ArgError(format!(
+            "{} must be positive, got '{}'",
+            option, value
+        )))
+    }
+}
+
+fn non_negative<T: FromStr + PartialOrd + Default>(
+    option: &str,
+    value: &str,
+) -> Result<T, ArgError> {
+    let n: T = parse(option, value)?;
+    if n >= T::default() {
+        Ok(n)
+    } else {
+        Err(ArgError(format!(
+            "{} must not be negative, got '{}'",
+            option, value
+        )))
+    }
+}
+
+fn finite(option: &str, value: &str) -> Result<f64, ArgError> {
+    let x: f64 = parse(option, value)?;
+    if x.is_finite() {
+        Ok(x)
+    } else {
+        Err(ArgError(format!(
+            "invalid value '{}' for {}",
+            value, option
+        )))
+    }
+}
+
+fn vector(option: &str, value: &str) -> Result<Vec3, ArgError> {
+    let parts = value
+        .split(',')
+        .map(|s| finite(option, s.trim()))
+        .collect::<Result<Vec<_>, _>>()?;
+    match parts[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err(ArgError(format!(
+            "{} expects three comma-separated numbers, got '{}'",
+            option, value
+        ))),
+    }
+}
+
+fn output(value: &str) -> Result<(PathBuf, OutputFormat), ArgError> {
+    let path = PathBuf::from(value);
+    let format = OutputFormat::from_path(&path).ok_or_else(|| {
+        ArgError(format!(
+            "unsupported output format '{}', expected .png, .jpg, .ppm or .hdr",
+            value
+        ))
+    })?;
+    Ok((path, format))
+}
+
+pub fn parse_arguments(args: impl IntoIterator<Item = String>) -> Result<ProgramArgs, ArgError> {
+    let mut it = args.into_iter();
+    let mut args = ProgramArgs::default();
+
+    while let Some(option) = it.next() {
+        let option = option.as_str();
+        if option == "-h" || option == "--help" {
+            args.help = true;
+            return Ok(args);
+        }
+        match option {
+            "--bvh-tree" => {
+                args.bvh_tree = true;
+                continue;
+            }
+            "--no-light-sampling" => {
+                args.light_sampling = false;
+                continue;
+            }
+            _ => (),
+        }
+
+        if !option.starts_with('-') {
+            return Err(ArgError(format!("unexpected argument '{}'", option)));
+        }
+        let value = it
+            .next()
+            .ok_or_else(|| ArgError(format!("missing value for {}", option)))?;
+        let value = value.as_str();
+        match option {
+            "-s" | "--scene" => {
+                args.scene = parse(option, value)?;
+                if !(1..=SCENE_COUNT).contains(&args.scene) {
+                    return Err(ArgError(format!(
+                        "unknown scene '{}', expected a number from 1 to {}",
+                        value, SCENE_COUNT
+                    )));
+                }
+            }
+            "--scene-file" => args.scene_file = Some(PathBuf::from(value)),
+            "-w" | "--width" => args.width = Some(positive(option, value)?),
+            "-H" | "--height" => args.height = Some(positive(option, value)?),
+            "--spp" => args.samples_per_pixel = Some(positive(option, value)?),
+            "-d" | "--max-depth" => args.max_depth = Some(non_negative(option, value)?),
+            "--roulette-depth" => args.roulette_depth = non_negative(option, value)?,
+            "-o" | "--output" => args.output = Some(output(value)?),
+            "--samples-image" => args.samples_image = Some(output(value)?),
+            "--adaptive" => args.adaptive_threshold = positive(option, value)?,
+            "--min-spp" => args.min_samples = positive(option, value)?,
+            "--look-from" => args.camera.look_from = Some(vector(option, value)?),
+            "--look-at" => args.camera.look_at = Some(vector(option, value)?),
+            "--vup" => args.camera.vup = Some(vector(option, value)?),
+            "--vfov" => {
+                let vfov = finite(option, value)?;
+                if !(vfov > 0.0 && vfov < 180.0) {
+                    return Err(ArgError(format!(
+                        "--vfov must be between 0 and 180 degrees, got '{}'",
+                        value
+                    )));
+                }
+                args.camera.vfov = Some(vfov);
+            }
+            "--aperture" => {
+                let aperture = finite(option, value)?;
+                if aperture < 0.0 {
+                    return Err(ArgError(format!(
+                        "--aperture must not be negative, got '{}'",
+                        value
+                    )));
+                }
+                args.camera.aperture = Some(aperture);
+            }
+            "--focus-dist" => {
+                let focus_dist = finite(option, value)?;
+                if focus_dist <= 0.0 {
+                    return Err(ArgError(format!(
+                        "--focus-dist must be positive, got '{}'",
+                        value
+                    )));
+                }
+                args.camera.focus_dist = Some(focus_dist);
+            }
+            "-j" | "--threads" => args.threads = Some(positive(option, value)?),
+            "--seed" => args.seed = parse(option, value)?,
+            "--integrator" => {
+                args.integrator = match value {
+                    "path" => IntegratorType::Path,
+                    "ao" => IntegratorType::AmbientOcclusion,
+                    "normals" => IntegratorType::Normals,
+                    "albedo" => IntegratorType::Albedo,
+                    "uv" => IntegratorType::Uv,
+                    "depth" => IntegratorType::Depth,
+                    "position" => IntegratorType::Position,
+                    _ => {
+                        return Err(ArgError(format!(
+                            "unknown integrator '{}', expected 'path', 'ao', 'normals', \
+                             'albedo', 'uv', 'depth' or 'position'",
+                            value
+                        )))
+                    }
+                }
+            }
+            "--ao-distance" => args.ao_distance = positive(option, value)?,
+            "--sampler" => {
+                args.sampler = match value {
+                    "independent" => SamplerType::Independent,
+                    "stratified" => SamplerType::Stratified,
+                    "halton" => SamplerType::Halton,
+                    "sobol" => SamplerType::Sobol,
+                    _ => {
+                        return Err(ArgError(format!(
+                            "unknown sampler '{}', expected 'independent', 'stratified', \
+                             'halton' or 'sobol'",
+                            value
+                        )))
+                    }
+                }
+            }
+            "--tile-size" => args.tile_size = positive(option, value)?,
+            "--tile-order" => {
+                args.tile_order = match value {
+                    "spiral" => TileOrder::Spiral,
+                    "scanline" => TileOrder::Scanline,
+                    _ => {
+                        return Err(ArgError(format!(
+                            "unknown tile order '{}', expected 'spiral' or 'scanline'",
+                            value
+                        )))
+                    }
+                }
+            }
+            "--pass-spp" => args.pass_samples = positive(option, value)?,
+            "--checkpoint" => args.checkpoint = Some(PathBuf::from(value)),
+            "--checkpoint-interval" => {
+                let seconds = finite(option, value)?;
+                if seconds < 0.0 {
+                    return Err(ArgError(format!(
+                        "--checkpoint-interval must not be negative, got '{}'",
+                        value
+                    )));
+                }
+                args.checkpoint_interval = Duration::from_secs_f64(seconds);
+            }
+            "--resume" => args.resume = Some(PathBuf::from(value)),
+            "--bvh" => {
+                args.bvh_split = match value {
+                    "median" => BvhSplit::Median,
+                    "sah" => BvhSplit::Sah,
+                    _ => {
+                        return Err(ArgError(format!(
+                            "unknown BVH split '{}', expected 'median' or 'sah'",
+                            value
+                        )))
+                    }
+                }
+            }
+            _ => return Err(ArgError(format!("unknown option '{}'", option))),
+        }
+    }
+
+    Ok(args)
+}
+
+/// FNV-1a hash of `bytes`, the same on every run and platform.
+fn content_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |h, &b| {
+        (h ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+impl ProgramArgs {
+    /// Identifies the scene and camera being rendered, to tell whether a checkpoint belongs
+    /// to them. Scene files are identified by their contents as well as their path, so that
+    /// editing one invalidates its checkpoints. Only the scene file's own bytes are hashed:
+    /// edits to the OBJ, MTL or texture files it references are not detected.
+    pub fn scene_label(&self, scene: &Scene) -> io::Result<String> {
+        let source = match &self.scene_file {
+            Some(path) => {
+                let contents = fs::read(path)?;
+                format!("file {} {:016x}", path.display(), content_hash(&contents))
+            }
+            None => format!("scene {}", self.scene),
+        };
+        Ok(format!("{} {:?}", source, scene.camera))
+    }
+
+    /// Applies the command-line overrides to the scene's camera and returns the render
+    /// settings.
+    pub fn apply(&self, scene: &mut Scene) -> Result<RenderSettings, ArgError> {
+        match (self.width, self.height) {
+            (Some(width), Some(height)) => {
+                scene.image_width = width;
+                scene.aspect_ratio = width as f64 / height as f64;
+            }
+            (Some(width), None) => scene.image_width = width,
+            (None, Some(height)) => {
+                scene.image_width = ((height as f64 * scene.aspect_ratio).round() as i32).max(1)
+            }
+            (None, None) => {}
+        }
+        if let Some(spp) = self.samples_per_pixel {
+            scene.samples_per_pixel = spp;
+        }
+        if let Some(max_depth) = self.max_depth {
+            scene.max_depth = max_depth;
+        }
+
+        let args = &self.camera;
+        let camera = &mut scene.camera;
+        let moved = args.look_from.is_some() || args.look_at.is_some();
+        camera.look_from = args.look_from.unwrap_or(camera.look_from);
+        camera.look_at = args.look_at.unwrap_or(camera.look_at);
+        camera.vup = args.vup.unwrap_or(camera.vup);
+        camera.vfov = args.vfov.unwrap_or(camera.vfov);
+        camera.aperture = args.aperture.unwrap_or(camera.aperture);
+        camera.focus_dist = match args.focus_dist {
+            Some(focus_dist) => focus_dist,
+            None if moved => (camera.look_at - camera.look_from).mag(),
+            None => camera.focus_dist,
+        };
+
+        let view = camera.look_at - camera.look_from;
+        if view.mag() == 0.0 {
+            return Err(ArgError(
+                "--look-from and --look-at must differ".to_string(),
+            ));
+        }
+        if cross(&camera.vup, &view).mag() == 0.0 {
+            return Err(ArgError(
+                "--vup must not be parallel to the view direction".to_string(),
+            ));
+        }
+
+        let settings = RenderSettings {
+            bvh_split: self.bvh_split,
+            bvh_tree: self.bvh_tree,
+            roulette_depth: self.roulette_depth,
+            seed: self.seed,
+            sampler: self.sampler,
+            light_sampling: self.light_sampling,
+            integrator: self.integrator,
+            ao_distance: self.ao_distance,
+            tile_size: self.tile_size,
+            tile_order: self.tile_order,
+            pass_samples: self.pass_samples,
+            adaptive_threshold: self.adaptive_threshold,
+            min_samples: self.min_samples,
+            ..RenderSettings::from_scene(scene)
+        };
+        settings
+            .validate()
+            .map_err(|err| ArgError(err.to_string()))?;
+
+        Ok(settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Result<ProgramArgs, ArgError> {
+        parse_arguments(s.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn test_parse_arguments() {
+        let a = args("-s 3 -w 320 -H 240 --spp 16 -d 8 -j 2 --seed 7 --look-from 1,2,3 -o x.hdr")
+            .unwrap();
+        assert_eq!(a.scene, 3);
+        assert_eq!((a.width, a.height), (Some(320), Some(240)));
+        assert_eq!((a.samples_per_pixel, a.max_depth), (Some(16), Some(8)));
+        assert_eq!((a.threads, a.seed), (Some(2), 7));
+        assert_eq!(a.camera.look_from.unwrap().as_tuple(), (1.0, 2.0, 3.0));
+        assert_eq!(a.output.unwrap().1, OutputFormat::Hdr);
+        assert!(args("--help").unwrap().help);
+    }
+
+    #[test]
+    fn test_image_too_small() {
+        for (bad, good) in [
+            ("-w 1", "-w 2 -H 2"),
+            ("-w 400 -H 1", "-w 400 -H 2"),
+            ("-H 1", "-w 2 -H 2"),
+        ] {
+            let mut scene = ray_tracing_weekend::scenes::builtin_scene(2, 0).unwrap();
+            let err = args(bad).unwrap().apply(&mut scene).err();
+            assert!(err.is_some(), "{} should be rejected", bad);
+            let settings = args(good).unwrap().apply(&mut scene).unwrap();
+            assert!(settings.image_width >= 2 && settings.image_height >= 2);
+        }
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        for bad in [
+            "-s 0",
+            "-s x",
+            "--spp 0",
+            "-d -1",
+            "--roulette-depth x",
+            "-w -5",
+            "--vfov 180",
+            "--look-at 1,2",
+            "-o image.bmp",
+            "--bvh best",
+            "--tile-size 0",
+            "--tile-order random",
+            "--sampler sobel",
+            "--integrator whitted",
+            "--ao-distance 0",
+            "--checkpoint-interval -1",
+            "--adaptive 0",
+            "--samples-image x.tiff",
+            "--frobnicate 1",
+            "--spp",
+            "stray",
+        ] {
+            assert!(args(bad).is_err(), "{} should be rejected", bad);
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable, SharedHittable};
+use crate::material::{Isotropic, SharedMaterial};
+use crate::ray::Ray;
+use crate::texture::SharedTexture;
+use crate::util::*;
+use crate::vec3::Vec3;
+
+/// A volume of uniform density filling a convex boundary, e.g. smoke or fog. How far into it
+/// a ray scatters comes from the ray's `medium_sample`.
+pub struct ConstantMedium {
+    boundary: SharedHittable,
+    neg_inv_density: f64,
+    phase_function: SharedMaterial,
+}
+
+impl ConstantMedium {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(boundary: SharedHittable, density: f64, albedo: SharedTexture) -> SharedHittable {
+        Box::new(ConstantMedium {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function: Isotropic::new(albedo),
+        })
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        // Find where the ray enters and exits the boundary, even if it starts inside
+        let enter = self.boundary.hit(r, f64::NEG_INFINITY, f64::INFINITY)?;
+        let exit = self.boundary.hit(r, enter.t + 0.0001, f64::INFINITY)?;
+
+        let t_enter = enter.t.max(t_min).max(0.0);
+        let t_exit = exit.t.min(t_max);
+        if t_enter >= t_exit {
+            return None;
+        }
+
+        let ray_length = r.direction.mag();
+        let distance_inside_boundary = (t_exit - t_enter) * ray_length;
+        let hit_distance = self.neg_inv_density * r.medium_sample.ln();
+        if hit_distance > distance_inside_boundary {
+            return None;
+        }
+
+        let t = t_enter + hit_distance / ray_length;
+        // The normal is arbitrary since the isotropic phase function ignores it
+        Some(HitRecord::new(
+            r.at(t),
+            t,
+            0.0,
+            0.0,
+            r,
+            &Vec3::new(1.0, 0.0, 0.0),
+            self.phase_function.clone(),
+        ))
+    }
+
+    fn bounding_box(&self, t0: Time, t1: Time) -> Option<AABB> {
+        self.boundary.bounding_box(t0, t1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cube::Cube;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColor;
+
+    /// The hit distances along `r` over evenly spread medium samples.
+    fn hits(medium: &SharedHittable, r: Ray, n: usize) -> Vec<f64> {
+        (0..n)
+            .filter_map(|i| {
+                let r = Ray::sampled(r.origin, r.direction, r.time, (i as f64 + 0.5) / n as f64);
+                medium.hit(&r, 0.001, f64::INFINITY).map(|rec| rec.t)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_hit_probability_through_slab() {
+        let white = SolidColor::new(Color::one());
+        let (density, thickness) = (0.7, 2.0);
+        let slab = Cube::new(
+            Point3::new(0.0, -5.0, -5.0),
+            Point3::new(thickness, 5.0, 5.0),
+            Lambertian::new(white.clone()),
+        );
+        let medium = ConstantMedium::new(slab, density, white);
+
+        // A ray of length 2 per unit of t, so the hits must be scaled to distances
+        let r = Ray::new(Point3::new(-1.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 0.0), 0.0);
+        let n = 10000;
+        let t = hits(&medium, r, n);
+        let expected = 1.0 - (-density * thickness).exp();
+        assert!((t.len() as f64 / n as f64 - expected).abs() < 1e-3);
+        assert!(t.iter().all(|t| (0.5..=1.5).contains(t)));
+    }
+
+    #[test]
+    fn test_ray_starting_inside() {
+        let white = SolidColor::new(Color::one());
+        let sphere = Sphere::new(Point3::zero(), 1.0, Lambertian::new(white.clone()));
+        let medium = ConstantMedium::new(sphere, 2.0, white);
+
+        let r = Ray::new(Point3::zero(), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        let n = 10000;
+        let t = hits(&medium, r, n);
+        let expected = 1.0 - (-2.0f64).exp();
+        assert!((t.len() as f64 / n as f64 - expected).abs() < 1e-3);
+        // Scattering only ahead of the origin, within the boundary
+        assert!(t.iter().all(|t| *t >= 0.001 && *t <= 1.0));
+    }
+}
@@ -12,6 +12,7 @@ pub struct Cube {
 }
 
 impl Cube {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(min: Point3, max: Point3, material: SharedMaterial) -> SharedHittable {
         let sides = vec![
             Rect2D::new_xy(min.x, max.x, min.y, max.y, max.z, material.clone()),
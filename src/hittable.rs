@@ -4,16 +4,20 @@ use crate::ray::Ray;
 use crate::util::Point3;
 use crate::util::Time;
 use crate::vec3::{dot, Vec3};
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct HitRecord {
     pub p: Point3,
     pub normal: Vec3,
-    t: f64,
+    pub t: f64,
     pub u: f64,
     pub v: f64,
     pub front_face: bool,
     pub material: SharedMaterial,
+    /// The wavelength in nanometres a path carries once dispersion has split its light, or
+    /// `None` while it carries RGB.
+    pub wavelength: Option<f64>,
 }
 
 impl HitRecord {
@@ -40,6 +44,7 @@ impl HitRecord {
             v,
             front_face,
             material,
+            wavelength: None,
         }
     }
 }
@@ -47,6 +52,22 @@ impl HitRecord {
 pub trait Hittable {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
     fn bounding_box(&self, t0: Time, t1: Time) -> Option<AABB>;
+
+    /// Whether the object emits light and supports `sample_direction` and `pdf_value`, so that
+    /// it can be sampled directly.
+    fn is_light(&self) -> bool {
+        false
+    }
+
+    /// Maps the uniform sample `u` to a direction from `origin` towards a point on the object.
+    fn sample_direction(&self, _origin: &Point3, _u: (f64, f64)) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+
+    /// Probability density, over solid angle, of `sample_direction` returning `direction`.
+    fn pdf_value(&self, _origin: &Point3, _direction: &Vec3) -> f64 {
+        0.0
+    }
 }
 
 pub type SharedHittable = Box<dyn Hittable + Send + Sync>;
@@ -82,3 +103,25 @@ impl Hittable for Vec<SharedHittable> {
         acc
     }
 }
+
+impl<T: Hittable + ?Sized> Hittable for Arc<T> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.as_ref().hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: Time, t1: Time) -> Option<AABB> {
+        self.as_ref().bounding_box(t0, t1)
+    }
+
+    fn is_light(&self) -> bool {
+        self.as_ref().is_light()
+    }
+
+    fn sample_direction(&self, origin: &Point3, u: (f64, f64)) -> Vec3 {
+        self.as_ref().sample_direction(origin, u)
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.as_ref().pdf_value(origin, direction)
+    }
+}
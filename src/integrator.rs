@@ -0,0 +1,616 @@
+use crate::hittable::{HitRecord, Hittable};
+use crate::light::LightList;
+use crate::material::SharedMaterial;
+use crate::ray::Ray;
+use crate::render::RenderSettings;
+use crate::sampler::Sampler;
+use crate::spectrum::{SampledWavelengths, WAVELENGTH_SAMPLES};
+use crate::util::*;
+use crate::vec3::*;
+use std::sync::Arc;
+
+/// Computes the value of a pixel sample from the camera ray through it: the radiance arriving
+/// along it, or one of the debug images below.
+pub trait Integrator {
+    fn ray_color(
+        &self,
+        r: &Ray,
+        world: &dyn Hittable,
+        lights: &LightList,
+        background: &Color,
+        sampler: &mut dyn Sampler,
+    ) -> Color;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IntegratorType {
+    /// Path tracing, the rendered image
+    Path,
+    /// The fraction of the hemisphere around the surface that is open within `ao_distance`
+    AmbientOcclusion,
+    /// The outward surface normal, with each component mapped from [-1, 1] to [0, 1]
+    Normals,
+    /// The surface color of the material
+    Albedo,
+    /// Texture coordinates in red and green
+    Uv,
+    /// Distance from the camera
+    Depth,
+    /// World space position
+    Position,
+}
+
+impl IntegratorType {
+    pub fn create(self, settings: &RenderSettings) -> Box<dyn Integrator + Send + Sync> {
+        match self {
+            IntegratorType::Path => Box::new(PathTracer {
+                max_depth: settings.max_depth,
+                roulette_depth: settings.roulette_depth,
+            }),
+            IntegratorType::AmbientOcclusion => Box::new(AmbientOcclusion {
+                distance: settings.ao_distance,
+            }),
+            IntegratorType::Normals => Box::new(Aov(|_: &Ray, rec: &HitRecord| {
+                let outward = if rec.front_face {
+                    rec.normal
+                } else {
+                    -rec.normal
+                };
+                0.5 * (outward + Color::one())
+            })),
+            IntegratorType::Albedo => {
+                Box::new(Aov(|_: &Ray, rec: &HitRecord| rec.material.albedo(rec)))
+            }
+            IntegratorType::Uv => Box::new(Aov(|_: &Ray, rec: &HitRecord| {
+                Color::new(rec.u, rec.v, 0.0)
+            })),
+            IntegratorType::Depth => Box::new(Aov(|r: &Ray, rec: &HitRecord| {
+                Color::full(rec.t * r.direction.mag())
+            })),
+            IntegratorType::Position => Box::new(Aov(|_: &Ray, rec: &HitRecord| rec.p)),
+        }
+    }
+}
+
+/// Follows paths for at most `max_depth` segments, or up to `UNLIMITED_DEPTH` if `max_depth`
+/// is 0. After `roulette_depth` bounces, paths are ended at random with a probability that
+/// rises as their throughput falls, and never below `1 - MAX_SURVIVAL`, and the paths that
+/// continue are weighted up to make up for it.
+pub struct PathTracer {
+    pub max_depth: i32,
+    pub roulette_depth: i32,
+}
+
+/// The most likely a path is to survive Russian roulette, so that paths losing no light, as
+/// between perfect mirrors, still end.
+const MAX_SURVIVAL: f64 = 0.95;
+
+/// The length paths are cut off at when `max_depth` is 0, in case roulette doesn't start until
+/// later.
+const UNLIMITED_DEPTH: i32 = 10_000;
+
+/// Light found along a path. It is the same at every wavelength until the path meets a
+/// dispersive material, so it is gathered in RGB; from then on the path carries a weight for
+/// each sampled wavelength, and the light it finds at each is converted to RGB at the end.
+struct PathRadiance {
+    rgb: Color,
+    spectral: [Color; WAVELENGTH_SAMPLES],
+}
+
+impl PathRadiance {
+    fn add(&mut self, weights: Option<&[f64; WAVELENGTH_SAMPLES]>, radiance: Color) {
+        match weights {
+            Some(weights) => {
+                for (spectral, &weight) in self.spectral.iter_mut().zip(weights) {
+                    *spectral += radiance * weight;
+                }
+            }
+            None => self.rgb += radiance,
+        }
+    }
+
+    fn to_rgb(&self, wavelengths: &SampledWavelengths) -> Color {
+        self.rgb + wavelengths.to_rgb(&self.spectral)
+    }
+}
+
+/// Scales the `weights` of `wavelengths` by how much more the dispersive material at `rec`
+/// scatters from `wo` to `wi` at each than at the hero that chose `wi`. Where `wi` depends on
+/// the wavelength, the companions are terminated and the hero stands for all of them.
+fn dispersed(
+    mut weights: [f64; WAVELENGTH_SAMPLES],
+    wavelengths: &SampledWavelengths,
+    rec: &HitRecord,
+    wi: &Vec3,
+    wo: &Vec3,
+) -> [f64; WAVELENGTH_SAMPLES] {
+    let ratios = wavelengths
+        .lambdas()
+        .map(|lambda| rec.material.wavelength_ratio(rec, wi, wo, lambda));
+    if ratios.iter().any(Option::is_none) {
+        if weights[1..].iter().any(|&w| w != 0.0) {
+            weights = [0.0; WAVELENGTH_SAMPLES];
+            weights[0] = WAVELENGTH_SAMPLES as f64;
+        }
+        return weights;
+    }
+    for (weight, ratio) in weights.iter_mut().zip(ratios) {
+        *weight *= ratio.unwrap();
+    }
+    weights
+}
+
+impl Integrator for PathTracer {
+    fn ray_color(
+        &self,
+        r: &Ray,
+        world: &dyn Hittable,
+        lights: &LightList,
+        background: &Color,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let wavelengths = SampledWavelengths::sample(sampler.get_1d());
+        self.trace(r, wavelengths, world, lights, background, sampler)
+    }
+}
+
+impl PathTracer {
+    /// The radiance arriving along `r`, followed at `wavelengths` where it splits by wavelength.
+    fn trace(
+        &self,
+        r: &Ray,
+        wavelengths: SampledWavelengths,
+        world: &dyn Hittable,
+        lights: &LightList,
+        background: &Color,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let PathTracer {
+            max_depth,
+            roulette_depth,
+        } = *self;
+        let max_depth = if max_depth > 0 {
+            max_depth
+        } else {
+            UNLIMITED_DEPTH
+        };
+
+        let mut radiance = PathRadiance {
+            rgb: Color::zero(),
+            spectral: [Color::zero(); WAVELENGTH_SAMPLES],
+        };
+        // The weights of the sampled wavelengths, once the path has met a dispersive material
+        let mut weights = None;
+        let mut throughput = Color::one();
+        let mut ray = *r;
+        // The density with which a diffuse bounce chose `ray`; emission it finds is then weighted
+        // against light sampling having found it
+        let mut scattering_pdf = None;
+        // The materials whose interiors the path is inside, innermost last, which absorb light
+        // along each segment it travels through them
+        let mut media: Vec<SharedMaterial> = Vec::new();
+        let mut depth = 0;
+
+        loop {
+            let mut rec = match world.hit(&ray, 0.001, f64::INFINITY) {
+                Some(rec) => rec,
+                None => {
+                    radiance.add(weights.as_ref(), throughput * *background);
+                    break;
+                }
+            };
+
+            if let Some(medium) = media.last() {
+                throughput = throughput * medium.transmittance(rec.t * ray.direction.mag());
+            }
+
+            let mut emitted = rec.material.emitted(rec.u, rec.v, &rec.p);
+            if let Some(pdf) = scattering_pdf {
+                if !emitted.near_zero() && !lights.is_empty() {
+                    emitted =
+                        emitted * power_heuristic(pdf, lights.pdf(&ray.origin, &ray.direction));
+                }
+            }
+            radiance.add(weights.as_ref(), throughput * emitted);
+
+            depth += 1;
+            if depth >= max_depth {
+                break;
+            }
+
+            let dispersive = rec.material.is_dispersive();
+            if dispersive && weights.is_none() {
+                weights = Some([1.0; WAVELENGTH_SAMPLES]);
+            }
+            if weights.is_some() {
+                rec.wavelength = Some(wavelengths.hero());
+            }
+
+            let wo = -ray.direction.normalized();
+            let scatter = match rec.material.sample(&rec, &wo, sampler) {
+                Some(scatter) => scatter,
+                None => break,
+            };
+            if scatter.is_specular {
+                scattering_pdf = None;
+            } else {
+                if let Some((direct, wi)) =
+                    sample_light(&ray, &rec, &wo, media.last(), world, lights, sampler)
+                {
+                    let direct_weights = match weights {
+                        Some(w) if dispersive => Some(dispersed(w, &wavelengths, &rec, &wi, &wo)),
+                        w => w,
+                    };
+                    radiance.add(direct_weights.as_ref(), throughput * direct);
+                }
+                scattering_pdf = Some(scatter.pdf);
+            }
+            throughput = throughput * scatter.weight;
+            if let (Some(w), true) = (weights, dispersive) {
+                weights = Some(dispersed(w, &wavelengths, &rec, &scatter.direction, &wo));
+            }
+            // Passing through the surface enters the object from the front and leaves it from
+            // the back
+            if !rec.material.is_volumetric() && dot(&scatter.direction, &rec.normal) < 0.0 {
+                if rec.front_face {
+                    media.push(rec.material.clone());
+                } else if let Some(i) = media
+                    .iter()
+                    .rposition(|m| std::ptr::addr_eq(Arc::as_ptr(m), Arc::as_ptr(&rec.material)))
+                {
+                    media.remove(i);
+                }
+            }
+            ray = Ray::sampled(rec.p, scatter.direction, ray.time, sampler.get_1d());
+
+            if depth >= roulette_depth {
+                let survival = throughput.max_element().min(MAX_SURVIVAL);
+                if sampler.get_1d() >= survival {
+                    break;
+                }
+                throughput = throughput / survival;
+            }
+        }
+
+        radiance.to_rgb(&wavelengths)
+    }
+}
+
+/// Power heuristic weight of a sample taken with density `pdf`, that another strategy could
+/// have taken with density `other_pdf` (Veach).
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
+
+/// Emission reaching the diffuse hit `rec` along a direction chosen by light sampling,
+/// weighted against the material's own scattering having chosen it, and that direction. The
+/// light is attenuated by `medium`, the interior the hit lies in.
+fn sample_light(
+    r: &Ray,
+    rec: &HitRecord,
+    wo: &Vec3,
+    medium: Option<&SharedMaterial>,
+    world: &dyn Hittable,
+    lights: &LightList,
+    sampler: &mut dyn Sampler,
+) -> Option<(Color, Vec3)> {
+    let wi = lights.sample(&rec.p, sampler)?.normalized();
+    let light_pdf = lights.pdf(&rec.p, &wi);
+    let scattering_pdf = rec.material.pdf(rec, &wi, wo);
+    if light_pdf <= 0.0 || scattering_pdf <= 0.0 {
+        return None;
+    }
+
+    // Whatever the shadow ray hits first is what it sees, so occluders block the light
+    let shadow_ray = Ray::sampled(rec.p, wi, r.time, sampler.get_1d());
+    let light_rec = world.hit(&shadow_ray, 0.001, f64::INFINITY)?;
+    let mut emitted = light_rec
+        .material
+        .emitted(light_rec.u, light_rec.v, &light_rec.p);
+    if let Some(medium) = medium {
+        emitted = emitted * medium.transmittance(light_rec.t);
+    }
+    let weight = power_heuristic(light_pdf, scattering_pdf);
+    Some((
+        emitted * rec.material.eval(rec, &wi, wo) * (weight / light_pdf),
+        wi,
+    ))
+}
+
+/// Cosine-weighted ambient occlusion: whether a random direction around the first hit
+/// escapes without hitting anything within `distance`.
+pub struct AmbientOcclusion {
+    pub distance: f64,
+}
+
+impl Integrator for AmbientOcclusion {
+    fn ray_color(
+        &self,
+        r: &Ray,
+        world: &dyn Hittable,
+        _lights: &LightList,
+        _background: &Color,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let rec = match world.hit(r, 0.001, f64::INFINITY) {
+            Some(rec) => rec,
+            None => return Color::one(),
+        };
+
+        let direction = rec.normal + sample_unit_vector(sampler.get_2d());
+        if direction.near_zero() {
+            return Color::one();
+        }
+        let occlusion_ray = Ray::sampled(rec.p, direction.normalized(), r.time, sampler.get_1d());
+        match world.hit(&occlusion_ray, 0.001, self.distance) {
+            Some(_) => Color::zero(),
+            None => Color::one(),
+        }
+    }
+}
+
+/// Shows a property of the first surface hit, and black where nothing is hit.
+struct Aov<F>(F);
+
+impl<F: Fn(&Ray, &HitRecord) -> Color> Integrator for Aov<F> {
+    fn ray_color(
+        &self,
+        r: &Ray,
+        world: &dyn Hittable,
+        _lights: &LightList,
+        _background: &Color,
+        _sampler: &mut dyn Sampler,
+    ) -> Color {
+        match world.hit(r, 0.001, f64::INFINITY) {
+            Some(rec) => (self.0)(r, &rec),
+            None => Color::zero(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::SharedHittable;
+    use crate::material::{Dielectric, DiffuseLight, Lambertian, Metal, SharedMaterial};
+    use crate::microfacet::refract_about;
+    use crate::sampler::SamplerType;
+    use crate::spectrum::Ior;
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColor;
+    use crate::triangle::Triangle;
+
+    #[test]
+    fn test_aovs() {
+        let white = Lambertian::new(SolidColor::new(Color::new(0.8, 0.6, 0.4)));
+        let world: Vec<SharedHittable> = vec![
+            Sphere::new(Point3::zero(), 1.0, white.clone()),
+            Sphere::new(Point3::new(0.0, 0.0, 10.0), 0.5, white),
+        ];
+        let settings = RenderSettings::default();
+        let mut sampler = SamplerType::Independent.create(0, 1);
+        let mut color = |integrator: IntegratorType, r: &Ray| {
+            let integrator = integrator.create(&settings);
+            sampler.start_pixel_sample(0, 0);
+            let lights = LightList::default();
+            let c = integrator.ray_color(r, &world, &lights, &Color::zero(), sampler.as_mut());
+            c.as_tuple()
+        };
+
+        let r = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -2.0), 0.0);
+        assert_eq!(color(IntegratorType::Normals, &r), (0.5, 0.5, 1.0));
+        assert_eq!(color(IntegratorType::Albedo, &r), (0.8, 0.6, 0.4));
+        assert_eq!(color(IntegratorType::Depth, &r), (4.0, 4.0, 4.0));
+        assert_eq!(color(IntegratorType::Position, &r), (0.0, 0.0, 1.0));
+        assert_eq!(color(IntegratorType::AmbientOcclusion, &r), (1.0, 1.0, 1.0));
+
+        // Inside the sphere the normal still points out, and nothing escapes
+        let inside = Ray::new(Point3::zero(), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        assert_eq!(color(IntegratorType::Normals, &inside), (1.0, 0.5, 0.5));
+        assert_eq!(
+            color(IntegratorType::AmbientOcclusion, &inside),
+            (0.0, 0.0, 0.0)
+        );
+
+        let miss = Ray::new(Point3::new(0.0, 5.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 0.0);
+        assert_eq!(color(IntegratorType::Uv, &miss), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_nested_absorption() {
+        // Index-matched glass absorbing half the light per unit, with a clear bubble in it
+        let absorption = Dielectric::absorption_for(Color::full(0.5), 1.0);
+        let world: Vec<SharedHittable> = vec![
+            Sphere::new(
+                Point3::zero(),
+                1.0,
+                Dielectric::tinted(1.0, 0.0, 0.0, absorption),
+            ),
+            Sphere::new(Point3::zero(), 0.5, Dielectric::new(1.0)),
+        ];
+        let settings = RenderSettings {
+            roulette_depth: i32::MAX,
+            ..RenderSettings::default()
+        };
+        let integrator = IntegratorType::Path.create(&settings);
+        let mut sampler = SamplerType::Independent.create(0, 1);
+        sampler.start_pixel_sample(0, 0);
+        let r = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let lights = LightList::default();
+        let c = integrator.ray_color(&r, &world, &lights, &Color::one(), sampler.as_mut());
+
+        // One unit of tinted glass is crossed, half before the bubble and half after it
+        assert!((c - Color::full(0.5)).mag() < 1e-9, "{:?}", c);
+    }
+
+    /// A glass prism pointing at the camera.
+    fn prism(glass: SharedMaterial) -> Vec<SharedHittable> {
+        let corners = [(-0.8, -0.5), (0.8, -0.5), (0.0, 0.8)];
+        let mut world = Vec::new();
+        for (i, &(x0, z0)) in corners.iter().enumerate() {
+            let (x1, z1) = corners[(i + 1) % 3];
+            let [a, b, c, d] = [(x0, -2.0, z0), (x1, -2.0, z1), (x1, 2.0, z1), (x0, 2.0, z0)]
+                .map(|(x, y, z)| Point3::new(x, y, z));
+            world.push(Triangle::new(a, b, c, glass.clone()));
+            world.push(Triangle::new(a, c, d, glass.clone()));
+        }
+        world
+    }
+
+    /// Mean of `n` paths through `world` from camera rays spread over a 2x2 square, lit by
+    /// `background`.
+    fn mean_color(world: &Vec<SharedHittable>, background: Color, n: u32) -> Color {
+        let settings = RenderSettings {
+            max_depth: 20,
+            ..RenderSettings::default()
+        };
+        let integrator = IntegratorType::Path.create(&settings);
+        let lights = LightList::default();
+        let mut sampler = SamplerType::Sobol.create(3, n);
+        let mut sum = Color::zero();
+        for i in 0..n {
+            sampler.start_pixel_sample(0, i);
+            let (x, y) = sampler.get_2d();
+            let origin = Point3::new(2.0 * x - 1.0, 2.0 * y - 1.0, 5.0);
+            let r = Ray::new(origin, Vec3::new(0.0, 0.0, -1.0), 0.0);
+            sum += integrator.ray_color(&r, world, &lights, &background, sampler.as_mut());
+        }
+        sum / n as f64
+    }
+
+    #[test]
+    fn test_non_dispersive_paths_ignore_wavelengths() {
+        // Paths through constant index glass and off metal stay in RGB, as they were before
+        // wavelengths were sampled
+        let light = DiffuseLight::new(SolidColor::new(Color::new(4.0, 3.0, 2.0)));
+        let white = Lambertian::new(SolidColor::new(Color::full(0.5)));
+        let world: Vec<SharedHittable> = vec![
+            Sphere::new(Point3::new(0.0, -100.5, 0.0), 100.0, white),
+            Sphere::new(Point3::new(-0.6, 0.0, 0.0), 0.5, Dielectric::new(1.5)),
+            Sphere::new(
+                Point3::new(0.6, 0.0, 0.0),
+                0.5,
+                Metal::new(Color::full(0.8), 0.2),
+            ),
+            Sphere::new(Point3::new(0.0, 2.0, 0.0), 0.5, light),
+        ];
+        let (world, lights) = LightList::collect(world);
+        let tracer = PathTracer {
+            max_depth: 10,
+            roulette_depth: 3,
+        };
+        for i in 0..256 {
+            let trace = |u: f64| {
+                let mut sampler = SamplerType::Independent.create(i, 1);
+                sampler.start_pixel_sample(0, 0);
+                let (x, y) = sampler.get_2d();
+                let r = Ray::new(
+                    Point3::new(0.0, 0.0, 5.0),
+                    Vec3::new(x - 0.5, y - 0.5, -5.0),
+                    0.0,
+                );
+                let wavelengths = SampledWavelengths::sample(u);
+                let c = tracer.trace(
+                    &r,
+                    wavelengths,
+                    &world,
+                    &lights,
+                    &Color::zero(),
+                    &mut *sampler,
+                );
+                c.as_tuple()
+            };
+            assert_eq!(trace(0.1), trace(0.7));
+        }
+    }
+
+    const BK7: Ior = Ior::Sellmeier {
+        b: [1.03961212, 0.231792344, 1.01046945],
+        c: [0.00600069867, 0.0200179144, 103.560653],
+    };
+
+    #[test]
+    fn test_dispersive_glass_averages_to_constant_ior() {
+        let constant = Dielectric::new(BK7.at(None));
+        let dispersive = Dielectric::dispersive(BK7, 0.0, 0.0, Color::zero());
+        let sphere =
+            |glass| -> Vec<SharedHittable> { vec![Sphere::new(Point3::zero(), 0.9, glass)] };
+        let cases = [
+            (
+                prism(constant.clone()),
+                prism(dispersive.clone()),
+                Color::new(1.0, 0.8, 0.6),
+            ),
+            (sphere(constant), sphere(dispersive), Color::one()),
+        ];
+        for (constant, dispersive, background) in cases {
+            let expected = mean_color(&constant, background, 1 << 14);
+            let mean = mean_color(&dispersive, background, 1 << 14);
+            assert!(
+                (mean - expected).mag() < 0.01 * expected.mag(),
+                "{:?} vs {:?}",
+                mean,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_companions_follow_reflections() {
+        let glass = Dielectric::dispersive(BK7, 0.0, 0.0, Color::zero());
+        let wavelengths = SampledWavelengths::sample(0.3);
+        let wo = Vec3::new(0.6, 0.8, 0.0);
+        let ray = Ray::new(Point3::zero() + wo, -wo, 0.0);
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let mut rec = HitRecord::new(Point3::zero(), 1.0, 0.5, 0.5, &ray, &normal, glass);
+        rec.wavelength = Some(wavelengths.hero());
+
+        // Reflection goes the same way at every wavelength, weighted by its Fresnel reflectance
+        let reflected = Vec3::new(-0.6, 0.8, 0.0);
+        let weights = dispersed(
+            [1.0; WAVELENGTH_SAMPLES],
+            &wavelengths,
+            &rec,
+            &reflected,
+            &wo,
+        );
+        assert_eq!(weights[0], 1.0);
+        assert!(
+            weights[1..].iter().all(|&w| w > 0.9 && w < 1.1 && w != 1.0),
+            "{:?}",
+            weights
+        );
+
+        // Refraction doesn't, so the hero carries on alone, once
+        let refracted = refract_about(&wo, &normal, 1.5).unwrap();
+        let hero = dispersed(weights, &wavelengths, &rec, &refracted, &wo);
+        assert_eq!(hero, [4.0, 0.0, 0.0, 0.0]);
+        assert_eq!(dispersed(hero, &wavelengths, &rec, &refracted, &wo), hero);
+    }
+
+    #[test]
+    fn test_unlimited_depth_ends_between_mirrors() {
+        // A closed mirror never absorbs any light, nor lets it escape
+        let mirror = Metal::new(Color::one(), 0.0);
+        let world: Vec<SharedHittable> = vec![Sphere::new(Point3::zero(), 1.0, mirror)];
+        let lights = LightList::default();
+        let r = Ray::new(Point3::zero(), Vec3::new(0.3, 0.5, 0.8), 0.0);
+        for roulette_depth in [3, i32::MAX] {
+            let integrator = PathTracer {
+                max_depth: 0,
+                roulette_depth,
+            };
+            let mut sampler = SamplerType::Independent.create(0, 16);
+            for i in 0..16 {
+                sampler.start_pixel_sample(0, i);
+                let c = integrator.ray_color(&r, &world, &lights, &Color::zero(), sampler.as_mut());
+                assert_eq!(c.as_tuple(), (0.0, 0.0, 0.0));
+            }
+        }
+    }
+}
@@ -0,0 +1,34 @@
+pub mod aabb;
+pub mod aarect;
+pub mod bvh;
+pub mod camera;
+pub mod checkpoint;
+pub mod constant_medium;
+pub mod cube;
+pub mod hittable;
+pub mod integrator;
+pub mod light;
+pub mod material;
+pub mod matrix;
+pub mod mesh;
+pub mod microfacet;
+pub mod moving_sphere;
+pub mod obj;
+pub mod output;
+pub mod perlin;
+pub mod ray;
+pub mod render;
+pub mod sampler;
+pub mod scene;
+pub mod scenes;
+pub mod spectrum;
+pub mod sphere;
+pub mod texture;
+pub mod tile;
+pub mod transform;
+pub mod triangle;
+pub mod util;
+pub mod vec3;
+
+pub use render::{Accumulator, Framebuffer, RenderSettings, Renderer, SettingsError};
+pub use scene::{CameraSettings, Scene};
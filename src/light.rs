@@ -0,0 +1,166 @@
+use crate::hittable::{Hittable, SharedHittable};
+use crate::sampler::Sampler;
+use crate::util::Point3;
+use crate::vec3::Vec3;
+use std::sync::Arc;
+
+/// The objects of a scene that emit light and can be sampled directly, shared with the BVH.
+///
+/// Only top-level objects (possibly wrapped in transforms) are considered: emitters inside a
+/// BVH, list or triangle mesh are still hit by scattered rays but are never sampled directly.
+#[derive(Default)]
+pub struct LightList {
+    lights: Vec<Arc<dyn Hittable + Send + Sync>>,
+}
+
+impl LightList {
+    /// Takes the lights among `objects` into a light list, returning the objects with the
+    /// lights replaced by shared references to them. Groups are not searched for lights.
+    pub fn collect(objects: Vec<SharedHittable>) -> (Vec<SharedHittable>, LightList) {
+        let mut lights = Vec::new();
+        let objects = objects
+            .into_iter()
+            .map(|object| -> SharedHittable {
+                if object.is_light() {
+                    let light: Arc<dyn Hittable + Send + Sync> = Arc::from(object);
+                    lights.push(light.clone());
+                    Box::new(light)
+                } else {
+                    object
+                }
+            })
+            .collect();
+        (objects, LightList { lights })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    /// Picks one light uniformly and samples a direction from `origin` towards it, or returns
+    /// `None` if there are no lights.
+    pub fn sample(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        let n = self.lights.len();
+        if n == 0 {
+            return None;
+        }
+        let i = ((sampler.get_1d() * n as f64) as usize).min(n - 1);
+        Some(self.lights[i].sample_direction(origin, sampler.get_2d()))
+    }
+
+    /// Probability density, over solid angle, of `sample` returning `direction`.
+    pub fn pdf(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        if self.lights.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self
+            .lights
+            .iter()
+            .map(|light| light.pdf_value(origin, direction))
+            .sum();
+        sum / self.lights.len() as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aarect::Rect2D;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::matrix::Mat4;
+    use crate::ray::Ray;
+    use crate::sampler::SamplerType;
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColor;
+    use crate::transform::{RotateY, Transform, Translate};
+    use crate::util::Color;
+    use crate::vec3::sample_unit_vector;
+
+    /// Estimates the integral of the light list's pdf over all directions from `origin`.
+    fn pdf_integral(lights: &LightList, origin: &Point3) -> f64 {
+        let mut sampler = SamplerType::Sobol.create(1, 1 << 14);
+        let n = 1 << 14;
+        let mut total = 0.0;
+        for i in 0..n {
+            sampler.start_pixel_sample(0, i);
+            let direction = sample_unit_vector(sampler.get_2d());
+            total += lights.pdf(origin, &direction) * 4.0 * std::f64::consts::PI;
+        }
+        total / n as f64
+    }
+
+    #[test]
+    fn test_pdf_integrates_to_one() {
+        let light = DiffuseLight::new(SolidColor::new(Color::one()));
+        let white = Lambertian::new(SolidColor::new(Color::one()));
+        let objects = vec![
+            Rect2D::new_xz(-1.0, 1.0, -0.5, 2.0, 3.0, light.clone()),
+            Sphere::new(Point3::new(2.0, -1.0, 0.5), 0.75, light),
+            Sphere::new(Point3::zero(), 0.5, white),
+        ];
+        let (objects, lights) = LightList::collect(objects);
+        assert_eq!((objects.len(), lights.len()), (3, 2));
+
+        let mut sampler = SamplerType::Independent.create(1, 1);
+        let origin = Point3::zero();
+        assert!(LightList::default()
+            .sample(&origin, &mut *sampler)
+            .is_none());
+        assert_eq!(
+            LightList::default().pdf(&origin, &Vec3::new(0.0, 1.0, 0.0)),
+            0.0
+        );
+
+        // Estimate the integral of the pdf over all directions, from outside and inside a light
+        for origin in [Point3::new(0.0, 1.0, 0.0), Point3::new(2.0, -1.2, 0.5)] {
+            let integral = pdf_integral(&lights, &origin);
+            assert!((integral - 1.0).abs() < 0.02, "integral {}", integral);
+        }
+    }
+
+    #[test]
+    fn test_wrapped_lights() {
+        let light = DiffuseLight::new(SolidColor::new(Color::one()));
+        let white = Lambertian::new(SolidColor::new(Color::one()));
+        let stretch = Mat4::translate(&Vec3::new(0.0, 0.0, -3.0))
+            * Mat4::rotate(&Vec3::new(1.0, 0.0, 0.0), 30.0)
+            * Mat4::scale(&Vec3::new(2.0, 0.5, 1.0));
+        let objects = vec![
+            Translate::new(
+                Sphere::new(Point3::zero(), 0.5, light.clone()),
+                Vec3::new(3.0, 0.0, 0.0),
+            ),
+            RotateY::new(
+                Rect2D::new_xz(-1.0, 1.0, -0.5, 0.5, 2.0, light.clone()),
+                30.0,
+            ),
+            Transform::new(Rect2D::new_xy(-0.5, 0.5, -0.5, 0.5, 0.0, light), stretch),
+            Translate::new(
+                Sphere::new(Point3::zero(), 0.5, white),
+                Vec3::new(-3.0, 0.0, 0.0),
+            ),
+        ];
+        let (objects, lights) = LightList::collect(objects);
+        assert_eq!((objects.len(), lights.len()), (4, 3));
+
+        let origin = Point3::new(0.0, 0.2, 0.1);
+        let integral = pdf_integral(&lights, &origin);
+        assert!((integral - 1.0).abs() < 0.02, "integral {}", integral);
+
+        // Sampled directions lead to the lights, with the density `pdf` reports
+        let mut sampler = SamplerType::Independent.create(2, 1);
+        for i in 0..256 {
+            sampler.start_pixel_sample(0, i);
+            let direction = lights.sample(&origin, &mut *sampler).unwrap();
+            let rec = objects
+                .hit(&Ray::new(origin, direction, 0.0), 0.001, f64::INFINITY)
+                .unwrap();
+            assert!(rec.material.is_emissive());
+            assert!(lights.pdf(&origin, &direction) > 0.0);
+        }
+    }
+}
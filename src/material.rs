@@ -1,15 +1,95 @@
 use crate::hittable::HitRecord;
-use crate::ray::Ray;
-use crate::texture::SharedTexture;
+use crate::microfacet::{reflect_about, refract_about, Frame, Ggx};
+use crate::sampler::Sampler;
+use crate::spectrum::Ior;
+use crate::texture::{SharedTexture, SolidColor};
 use crate::util::*;
 use crate::vec3::*;
+use std::f64::consts::PI;
 use std::sync::Arc;
 
+/// A direction chosen by `Material::sample`.
+#[derive(Debug, Copy, Clone)]
+pub struct ScatterRecord {
+    /// Unit direction the light scatters from, away from the surface
+    pub direction: Vec3,
+    /// Probability density, over solid angle, of choosing `direction`
+    pub pdf: f64,
+    /// `eval / pdf`, the factor the radiance from `direction` is scaled by
+    pub weight: Color,
+    /// The direction was chosen from a delta distribution, such as a perfect mirror, so `pdf`
+    /// is infinite and `eval` and light sampling can never find it
+    pub is_specular: bool,
+}
+
+impl ScatterRecord {
+    fn specular(direction: Vec3, weight: Color) -> ScatterRecord {
+        ScatterRecord {
+            direction,
+            pdf: 1.0,
+            weight,
+            is_specular: true,
+        }
+    }
+}
+
+/// Describes how light scatters at a hit. Directions are unit vectors in world space pointing
+/// away from the hit: `wo` towards the viewer and `wi` towards where the light comes from.
 pub trait Material {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)>;
+    /// Chooses a direction `wi` to continue a path arriving from `wo`.
+    fn sample(
+        &self,
+        rec: &HitRecord,
+        wo: &Vec3,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord>;
+    /// The BSDF times the cosine at `wi`; 0 for specular materials.
+    fn eval(&self, _rec: &HitRecord, _wi: &Vec3, _wo: &Vec3) -> Color {
+        Color::zero()
+    }
+    /// Probability density, over solid angle, of `sample` choosing `wi`.
+    fn pdf(&self, _rec: &HitRecord, _wi: &Vec3, _wo: &Vec3) -> f64 {
+        0.0
+    }
+    /// The overall color of the material at `rec`, for debug images.
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::zero()
+    }
+    /// Fraction of the light that survives travelling `distance` inside the material.
+    fn transmittance(&self, _distance: f64) -> Color {
+        Color::one()
+    }
+
+    /// Whether the material scatters light inside a volume rather than at a surface, so that
+    /// its scattering never enters or leaves an object.
+    fn is_volumetric(&self) -> bool {
+        false
+    }
+
+    /// Whether scattering depends on the wavelength, so that paths must pick one to follow.
+    fn is_dispersive(&self) -> bool {
+        false
+    }
+
+    /// How much more light a dispersive material scatters from `wo` to `wi` at `wavelength`
+    /// than at `rec.wavelength`, or `None` if `wi` itself depends on the wavelength, as for
+    /// refraction, so that it can only be followed at `rec.wavelength`.
+    fn wavelength_ratio(
+        &self,
+        _rec: &HitRecord,
+        _wi: &Vec3,
+        _wo: &Vec3,
+        _wavelength: f64,
+    ) -> Option<f64> {
+        Some(1.0)
+    }
+
     fn emitted(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
         Color::zero()
     }
+    fn is_emissive(&self) -> bool {
+        false
+    }
 }
 
 pub type SharedMaterial = Arc<dyn Material + Sync + Send>;
@@ -19,96 +99,621 @@ pub struct Lambertian {
 }
 
 impl Lambertian {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(albedo: SharedTexture) -> SharedMaterial {
         Arc::new(Lambertian { albedo })
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let mut scatter_direction = rec.normal + random_unit_vector();
-        if scatter_direction.near_zero() {
-            scatter_direction = rec.normal;
+    fn sample(
+        &self,
+        rec: &HitRecord,
+        wo: &Vec3,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let mut direction = rec.normal + sample_unit_vector(sampler.get_2d());
+        if direction.near_zero() {
+            direction = rec.normal;
         }
+        let direction = direction.normalized();
+
+        // Cosine-weighted, so the weight is the albedo
+        Some(ScatterRecord {
+            direction,
+            pdf: self.pdf(rec, &direction, wo),
+            weight: self.albedo.value(rec.u, rec.v, &rec.p),
+            is_specular: false,
+        })
+    }
+
+    fn eval(&self, rec: &HitRecord, wi: &Vec3, _wo: &Vec3) -> Color {
+        self.albedo.value(rec.u, rec.v, &rec.p) * (dot(&rec.normal, wi).max(0.0) / PI)
+    }
 
-        let scattered = Ray::new(rec.p, scatter_direction, r_in.time);
-        let attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
-        Some((attenuation, scattered))
+    fn pdf(&self, rec: &HitRecord, wi: &Vec3, _wo: &Vec3) -> f64 {
+        dot(&rec.normal, wi).max(0.0) / PI
     }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(rec.u, rec.v, &rec.p)
+    }
+}
+
+/// Fresnel reflectance of a metal.
+#[derive(Debug, Copy, Clone)]
+pub enum ConductorFresnel {
+    /// Schlick's approximation from the reflectance at normal incidence
+    Schlick(Color),
+    /// Exact reflectance from the complex index of refraction `eta + i k` in each channel
+    Complex { eta: Color, k: Color },
+}
+
+impl ConductorFresnel {
+    fn reflectance(&self, cos_theta: f64) -> Color {
+        match self {
+            ConductorFresnel::Schlick(f0) => {
+                *f0 + (Color::one() - *f0) * (1.0 - cos_theta).clamp(0.0, 1.0).powi(5)
+            }
+            ConductorFresnel::Complex { eta, k } => Color::new(
+                fresnel_conductor(cos_theta, eta.x, k.x),
+                fresnel_conductor(cos_theta, eta.y, k.y),
+                fresnel_conductor(cos_theta, eta.z, k.z),
+            ),
+        }
+    }
+}
+
+/// Unpolarized reflectance of a conductor with complex index of refraction `eta + i k`.
+fn fresnel_conductor(cos_theta: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_theta.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let t0 = eta * eta - k * k - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+
+    let t1 = a2_plus_b2 + cos2;
+    let t2 = 2.0 * a * cos_theta;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    (rs + rp) / 2.0
 }
 
+/// A metal whose surface is made of GGX microfacets, mirror-like when smooth.
 pub struct Metal {
-    albedo: Color,
-    fuzz: f64,
+    fresnel: ConductorFresnel,
+    distribution: Ggx,
 }
 
 impl Metal {
+    /// A metal of color `albedo` at normal incidence, with `fuzz` as its roughness.
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(albedo: Color, fuzz: f64) -> SharedMaterial {
+        Metal::conductor(ConductorFresnel::Schlick(albedo), fuzz, fuzz)
+    }
+
+    /// A metal with roughness from 0 (a mirror) to 1 along the two tangents of the surface,
+    /// which differ for brushed metals.
+    pub fn conductor(
+        fresnel: ConductorFresnel,
+        roughness_x: f64,
+        roughness_y: f64,
+    ) -> SharedMaterial {
         Arc::new(Metal {
-            albedo,
-            fuzz: fuzz.min(1.0),
+            fresnel,
+            distribution: Ggx::from_roughness(roughness_x, roughness_y),
         })
     }
 }
 
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let reflected = reflect(&r_in.direction.normalized(), &rec.normal);
-        let scattered = Ray::new(
-            rec.p,
-            reflected + self.fuzz * random_in_unit_sphere(),
-            r_in.time,
-        );
-
-        if dot(&scattered.direction, &rec.normal) > 0.0 {
-            Some((self.albedo, scattered))
-        } else {
-            None
+    fn sample(
+        &self,
+        rec: &HitRecord,
+        wo: &Vec3,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let frame = Frame::new(&rec.normal);
+        let wo_local = frame.to_local(wo);
+        if wo_local.z <= 0.0 {
+            return None;
+        }
+
+        if self.distribution.is_smooth() {
+            let wi = reflect_about(wo, &rec.normal);
+            let weight = self.fresnel.reflectance(wo_local.z);
+            return Some(ScatterRecord::specular(wi, weight));
         }
+
+        let wm = self
+            .distribution
+            .sample_visible_normal(&wo_local, sampler.get_2d());
+        let wi_local = reflect_about(&wo_local, &wm);
+        if wi_local.z <= 0.0 {
+            return None;
+        }
+
+        let wi = frame.to_world(&wi_local);
+        let ggx = &self.distribution;
+        Some(ScatterRecord {
+            direction: wi,
+            pdf: self.pdf(rec, &wi, wo),
+            weight: self.fresnel.reflectance(dot(&wo_local, &wm))
+                * (ggx.g(&wo_local, &wi_local) / ggx.g1(&wo_local)),
+            is_specular: false,
+        })
+    }
+
+    fn eval(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> Color {
+        let frame = Frame::new(&rec.normal);
+        let (wi, wo) = (frame.to_local(wi), frame.to_local(wo));
+        if self.distribution.is_smooth() || wi.z <= 0.0 || wo.z <= 0.0 {
+            return Color::zero();
+        }
+
+        let wm = (wi + wo).normalized();
+        let ggx = &self.distribution;
+        self.fresnel.reflectance(dot(&wo, &wm)) * (ggx.d(&wm) * ggx.g(&wo, &wi) / (4.0 * wo.z))
+    }
+
+    fn pdf(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> f64 {
+        let frame = Frame::new(&rec.normal);
+        let (wi, wo) = (frame.to_local(wi), frame.to_local(wo));
+        if self.distribution.is_smooth() || wi.z <= 0.0 || wo.z <= 0.0 {
+            return 0.0;
+        }
+
+        // Change of variables from the microfacet normal to the reflected direction
+        let wm = (wi + wo).normalized();
+        self.distribution.visible_pdf(&wo, &wm) / (4.0 * dot(&wo, &wm))
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.fresnel.reflectance(1.0)
+    }
+}
+
+/// Unpolarized reflectance at a smooth interface where `eta` is the index of refraction on
+/// the far side relative to the side light arrives from at angle `cos_theta`.
+fn fresnel_dielectric(cos_theta: f64, eta: f64) -> f64 {
+    let cos_i = cos_theta.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
+}
+
+/// The dielectric microfacet normal that scatters `wo` into `wi`, and whether `wi` is reflected.
+/// Returns `None` when no microfacet facing both can.
+fn dielectric_half_vector(wi: &Vec3, wo: &Vec3, eta: f64) -> Option<(Vec3, bool)> {
+    if wi.z == 0.0 || wo.z <= 0.0 {
+        return None;
+    }
+    let reflected = wi.z > 0.0;
+    let mut wm = if reflected {
+        *wi + *wo
+    } else {
+        eta * *wi + *wo
+    };
+    if wm.near_zero() {
+        return None;
+    }
+    wm = wm.normalized();
+    if wm.z < 0.0 {
+        wm = -wm;
+    }
+    if dot(&wm, wi) * wi.z < 0.0 || dot(&wm, wo) <= 0.0 {
+        return None;
+    }
+    Some((wm, reflected))
+}
+
+/// The BSDF times the cosine at `wi` of rough dielectric microfacets, and the density of
+/// `sample_rough_dielectric` choosing `wi`, in the local frame.
+fn rough_dielectric(ggx: &Ggx, wi: &Vec3, wo: &Vec3, eta: f64) -> (f64, f64) {
+    let (wm, reflected) = match dielectric_half_vector(wi, wo, eta) {
+        Some(half) => half,
+        None => return (0.0, 0.0),
+    };
+    let r = fresnel_dielectric(dot(wo, &wm), eta);
+    let visible = ggx.visible_pdf(wo, &wm);
+
+    if reflected {
+        let f = ggx.d(&wm) * ggx.g(wo, wi) * r / (4.0 * wo.z);
+        (f, visible / (4.0 * dot(wo, &wm)) * r)
+    } else {
+        // Change of variables from the microfacet normal to the refracted direction
+        let denom = (dot(wi, &wm) + dot(wo, &wm) / eta).powi(2);
+        let dwm_dwi = dot(wi, &wm).abs() / denom;
+        // Radiance is compressed into a smaller solid angle going into a denser medium
+        let f =
+            ggx.d(&wm) * ggx.g(wo, wi) * (1.0 - r) * (dot(wo, &wm) * dwm_dwi) / (wo.z * eta * eta);
+        (f, visible * dwm_dwi * (1.0 - r))
+    }
+}
+
+/// Reflects or refracts `wo` off a visible microfacet, chosen by its Fresnel reflectance.
+fn sample_rough_dielectric(
+    ggx: &Ggx,
+    wo: &Vec3,
+    eta: f64,
+    sampler: &mut dyn Sampler,
+) -> Option<Vec3> {
+    let wm = ggx.sample_visible_normal(wo, sampler.get_2d());
+    let r = fresnel_dielectric(dot(wo, &wm), eta);
+    if sampler.get_1d() < r {
+        Some(reflect_about(wo, &wm))
+    } else {
+        refract_about(wo, &wm, eta)
     }
 }
 
+/// Glass and other clear materials, which reflect and refract light. Rough surfaces, such as
+/// frosted glass, are made of GGX microfacets. Light inside is absorbed following the
+/// Beer-Lambert law, which tints glass and liquids. An index of refraction that varies with
+/// wavelength disperses light into its colors.
 pub struct Dielectric {
-    index_of_refraction: f64,
+    index_of_refraction: Ior,
+    distribution: Ggx,
+    absorption: Color,
 }
 
 impl Dielectric {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(index_of_refraction: f64) -> SharedMaterial {
+        Dielectric::rough(index_of_refraction, 0.0, 0.0)
+    }
+
+    /// A dielectric with roughness from 0 (smooth) to 1 along the two tangents of the surface.
+    pub fn rough(index_of_refraction: f64, roughness_x: f64, roughness_y: f64) -> SharedMaterial {
+        Dielectric::tinted(index_of_refraction, roughness_x, roughness_y, Color::zero())
+    }
+
+    /// A rough dielectric absorbing the fraction `1 - exp(-absorption * d)` of the light in each
+    /// channel over a distance `d` inside it.
+    pub fn tinted(
+        index_of_refraction: f64,
+        roughness_x: f64,
+        roughness_y: f64,
+        absorption: Color,
+    ) -> SharedMaterial {
+        Dielectric::dispersive(
+            Ior::Constant(index_of_refraction),
+            roughness_x,
+            roughness_y,
+            absorption,
+        )
+    }
+
+    pub fn dispersive(
+        index_of_refraction: Ior,
+        roughness_x: f64,
+        roughness_y: f64,
+        absorption: Color,
+    ) -> SharedMaterial {
         Arc::new(Dielectric {
             index_of_refraction,
+            distribution: Ggx::from_roughness(roughness_x, roughness_y),
+            absorption,
         })
     }
 
-    fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
-        // Use Schlick's approximation for reflectance.
-        let r0 = ((1.0 - ref_idx) / (1.0 + ref_idx)).powi(2);
-        r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
+    /// The absorption coefficient that lets `transmittance` of the light through `distance`.
+    pub fn absorption_for(transmittance: Color, distance: f64) -> Color {
+        -transmittance.clamp(1.0e-6, 1.0).ln() / distance
+    }
+
+    /// Index of refraction beyond the surface relative to the side of the hit's normal.
+    fn relative_ior(&self, rec: &HitRecord) -> f64 {
+        self.relative_ior_at(rec, rec.wavelength)
+    }
+
+    fn relative_ior_at(&self, rec: &HitRecord, wavelength: Option<f64>) -> f64 {
+        let ior = self.index_of_refraction.at(wavelength);
+        if rec.front_face {
+            ior
+        } else {
+            1.0 / ior
+        }
     }
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let refraction_ratio = if rec.front_face {
-            1.0 / self.index_of_refraction
-        } else {
-            self.index_of_refraction
+    fn sample(
+        &self,
+        rec: &HitRecord,
+        wo: &Vec3,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let eta = self.relative_ior(rec);
+
+        if self.distribution.is_smooth() {
+            let r = fresnel_dielectric(dot(wo, &rec.normal), eta);
+            if sampler.get_1d() < r {
+                let wi = reflect_about(wo, &rec.normal);
+                return Some(ScatterRecord::specular(wi, Color::one()));
+            }
+            let wi = refract_about(wo, &rec.normal, eta)?;
+            return Some(ScatterRecord::specular(wi, Color::full(1.0 / (eta * eta))));
+        }
+
+        let frame = Frame::new(&rec.normal);
+        let wo_local = frame.to_local(wo);
+        let wi_local = sample_rough_dielectric(&self.distribution, &wo_local, eta, sampler)?;
+        let (f, pdf) = rough_dielectric(&self.distribution, &wi_local, &wo_local, eta);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(ScatterRecord {
+            direction: frame.to_world(&wi_local).normalized(),
+            pdf,
+            weight: Color::full(f / pdf),
+            is_specular: false,
+        })
+    }
+
+    fn eval(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> Color {
+        if self.distribution.is_smooth() {
+            return Color::zero();
+        }
+        let frame = Frame::new(&rec.normal);
+        let (wi, wo) = (frame.to_local(wi), frame.to_local(wo));
+        Color::full(rough_dielectric(&self.distribution, &wi, &wo, self.relative_ior(rec)).0)
+    }
+
+    fn pdf(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> f64 {
+        if self.distribution.is_smooth() {
+            return 0.0;
+        }
+        let frame = Frame::new(&rec.normal);
+        let (wi, wo) = (frame.to_local(wi), frame.to_local(wo));
+        rough_dielectric(&self.distribution, &wi, &wo, self.relative_ior(rec)).1
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.index_of_refraction.is_dispersive()
+    }
+
+    // Reflection goes the same way at every wavelength, with the Fresnel reflectance of the
+    // microfacet it came off as the only difference
+    fn wavelength_ratio(
+        &self,
+        rec: &HitRecord,
+        wi: &Vec3,
+        wo: &Vec3,
+        wavelength: f64,
+    ) -> Option<f64> {
+        if dot(wi, &rec.normal) <= 0.0 {
+            return None;
+        }
+        let cos_theta = dot(wo, &(*wi + *wo).normalized());
+        let hero = fresnel_dielectric(cos_theta, self.relative_ior(rec));
+        if hero <= 0.0 {
+            return Some(0.0);
+        }
+        let other = fresnel_dielectric(cos_theta, self.relative_ior_at(rec, Some(wavelength)));
+        Some(other / hero)
+    }
+
+    fn transmittance(&self, distance: f64) -> Color {
+        (-self.absorption * distance).exp()
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::one()
+    }
+}
+
+/// Schlick's Fresnel weight, `(1 - cos)^5`.
+fn schlick_weight(cos_theta: f64) -> f64 {
+    (1.0 - cos_theta).clamp(0.0, 1.0).powi(5)
+}
+
+/// Below this, the specular lobes of `Principled` would be perfect mirrors, which light
+/// sampling can't evaluate.
+const PRINCIPLED_MIN_ROUGHNESS: f64 = 0.05;
+const CLEARCOAT_ROUGHNESS: f64 = 0.1;
+
+/// A principled BSDF in the style of Disney's and Blender's, mixing a diffuse base with sheen,
+/// a specular layer, rough glass and a clear coat. Every parameter but `ior` is read from a
+/// texture; scalar parameters from 0 to 1 take the mean of its channels.
+pub struct Principled {
+    pub base_color: SharedTexture,
+    /// Blends from a dielectric to a metal whose specular color is the base color
+    pub metallic: SharedTexture,
+    pub roughness: SharedTexture,
+    /// Scales the dielectric specular reflectance, with 0.5 matching `ior`
+    pub specular: SharedTexture,
+    /// Weight of a second, glossy and uncolored specular layer on top
+    pub clearcoat: SharedTexture,
+    /// Soft reflection at grazing angles, as on cloth
+    pub sheen: SharedTexture,
+    /// Blends from the opaque base to glass tinted by the base color
+    pub transmission: SharedTexture,
+    pub ior: f64,
+}
+
+impl Default for Principled {
+    fn default() -> Principled {
+        Principled {
+            base_color: SolidColor::new(Color::full(0.8)),
+            metallic: SolidColor::new(Color::zero()),
+            roughness: SolidColor::new(Color::full(0.5)),
+            specular: SolidColor::new(Color::full(0.5)),
+            clearcoat: SolidColor::new(Color::zero()),
+            sheen: SolidColor::new(Color::zero()),
+            transmission: SolidColor::new(Color::zero()),
+            ior: 1.5,
+        }
+    }
+}
+
+/// The parameters of a `Principled` material at a hit, and the weights of its lobes.
+struct PrincipledLobes {
+    base_color: Color,
+    roughness: f64,
+    sheen: f64,
+    specular: Ggx,
+    specular_f0: Color,
+    clearcoat: Ggx,
+    /// Relative index of refraction of the glass
+    eta: f64,
+    /// Weights of the diffuse, specular, glass and clearcoat lobes, which are also the
+    /// probabilities of sampling them
+    weights: [f64; 4],
+}
+
+impl Principled {
+    fn lobes(&self, rec: &HitRecord) -> PrincipledLobes {
+        let scalar = |texture: &SharedTexture| {
+            (texture.value(rec.u, rec.v, &rec.p).sum() / 3.0).clamp(0.0, 1.0)
         };
+        let base_color = self.base_color.value(rec.u, rec.v, &rec.p);
+        let metallic = scalar(&self.metallic);
+        let roughness = scalar(&self.roughness).max(PRINCIPLED_MIN_ROUGHNESS);
+        let transmission = scalar(&self.transmission);
+
+        let dielectric_f0 =
+            ((self.ior - 1.0) / (self.ior + 1.0)).powi(2) * 2.0 * scalar(&self.specular);
+        let opaque = 1.0 - metallic;
+        PrincipledLobes {
+            base_color,
+            roughness,
+            sheen: scalar(&self.sheen),
+            specular: Ggx::from_roughness(roughness, roughness),
+            specular_f0: Color::full(dielectric_f0) * (1.0 - metallic) + base_color * metallic,
+            clearcoat: Ggx::from_roughness(CLEARCOAT_ROUGHNESS, CLEARCOAT_ROUGHNESS),
+            eta: if rec.front_face {
+                self.ior
+            } else {
+                1.0 / self.ior
+            },
+            weights: [
+                opaque * (1.0 - transmission),
+                1.0 - opaque * transmission,
+                opaque * transmission,
+                0.25 * scalar(&self.clearcoat),
+            ],
+        }
+    }
+}
+
+impl PrincipledLobes {
+    fn probabilities(&self) -> [f64; 4] {
+        let total: f64 = self.weights.iter().sum();
+        self.weights.map(|w| w / total)
+    }
+
+    /// The BSDF times the cosine at `wi`, and the density of sampling `wi`, in the local frame.
+    fn eval_pdf(&self, wi: &Vec3, wo: &Vec3) -> (Color, f64) {
+        let [diffuse, specular, glass, clearcoat] = self.weights;
+        let probabilities = self.probabilities();
+
+        let (glass_f, glass_pdf) = rough_dielectric(&self.specular, wi, wo, self.eta);
+        let mut f = self.base_color * (glass * glass_f);
+        let mut pdf = probabilities[2] * glass_pdf;
+        if wi.z <= 0.0 || wo.z <= 0.0 {
+            return (f, pdf);
+        }
+
+        let wm = (*wi + *wo).normalized();
+        let cos_d = dot(wi, &wm);
+
+        // Burley's diffuse, with retro-reflection at grazing angles on rough surfaces, and sheen
+        let (fl, fv) = (schlick_weight(wi.z), schlick_weight(wo.z));
+        let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+        let fd = (1.0 + (fd90 - 1.0) * fl) * (1.0 + (fd90 - 1.0) * fv);
+        let sheen_color = (Color::one() + self.base_color) / 2.0;
+        f += (self.base_color * (fd / PI) + sheen_color * (self.sheen * schlick_weight(cos_d)))
+            * (diffuse * wi.z);
+        pdf += probabilities[0] * wi.z / PI;
 
-        let unit_direction = r_in.direction.normalized();
+        for (ggx, weight, f0, probability) in [
+            (&self.specular, specular, self.specular_f0, probabilities[1]),
+            (
+                &self.clearcoat,
+                clearcoat,
+                Color::full(0.04),
+                probabilities[3],
+            ),
+        ] {
+            if weight > 0.0 {
+                let fresnel = f0 + (Color::one() - f0) * schlick_weight(dot(wo, &wm));
+                f += fresnel * (weight * ggx.d(&wm) * ggx.g(wo, wi) / (4.0 * wo.z));
+                pdf += probability * ggx.visible_pdf(wo, &wm) / (4.0 * dot(wo, &wm));
+            }
+        }
+        (f, pdf)
+    }
+}
 
-        let cos_theta = dot(&-unit_direction, &rec.normal).min(1.0);
-        let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
+impl Material for Principled {
+    fn sample(
+        &self,
+        rec: &HitRecord,
+        wo: &Vec3,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let lobes = self.lobes(rec);
+        let frame = Frame::new(&rec.normal);
+        let wo_local = frame.to_local(wo);
+        if wo_local.z <= 0.0 {
+            return None;
+        }
 
-        let direction = if refraction_ratio * sin_theta > 1.0
-            || Dielectric::reflectance(cos_theta, refraction_ratio) > rand()
-        {
-            reflect(&unit_direction, &rec.normal)
+        let [diffuse, specular, glass, _] = lobes.probabilities();
+        let u = sampler.get_1d();
+        let wi_local = if u < diffuse {
+            (Vec3::new(0.0, 0.0, 1.0) + sample_unit_vector(sampler.get_2d())).normalized()
+        } else if u < diffuse + specular {
+            let wm = lobes
+                .specular
+                .sample_visible_normal(&wo_local, sampler.get_2d());
+            reflect_about(&wo_local, &wm)
+        } else if u < diffuse + specular + glass {
+            sample_rough_dielectric(&lobes.specular, &wo_local, lobes.eta, sampler)?
         } else {
-            refract(&unit_direction, &rec.normal, refraction_ratio)
+            let wm = lobes
+                .clearcoat
+                .sample_visible_normal(&wo_local, sampler.get_2d());
+            reflect_about(&wo_local, &wm)
         };
 
-        let scattered = Ray::new(rec.p, direction, r_in.time);
-        Some((Color::one(), scattered))
+        let (f, pdf) = lobes.eval_pdf(&wi_local, &wo_local);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(ScatterRecord {
+            direction: frame.to_world(&wi_local).normalized(),
+            pdf,
+            weight: f / pdf,
+            is_specular: false,
+        })
+    }
+
+    fn eval(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> Color {
+        let frame = Frame::new(&rec.normal);
+        let (wi, wo) = (frame.to_local(wi), frame.to_local(wo));
+        self.lobes(rec).eval_pdf(&wi, &wo).0
+    }
+
+    fn pdf(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> f64 {
+        let frame = Frame::new(&rec.normal);
+        let (wi, wo) = (frame.to_local(wi), frame.to_local(wo));
+        self.lobes(rec).eval_pdf(&wi, &wo).1
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.base_color.value(rec.u, rec.v, &rec.p)
     }
 }
 
@@ -117,17 +722,271 @@ pub struct DiffuseLight {
 }
 
 impl DiffuseLight {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(emit: SharedTexture) -> SharedMaterial {
         Arc::new(DiffuseLight { emit })
     }
 }
 
 impl Material for DiffuseLight {
-    fn scatter(&self, _: &Ray, _: &HitRecord) -> Option<(Color, Ray)> {
+    fn sample(&self, _: &HitRecord, _: &Vec3, _: &mut dyn Sampler) -> Option<ScatterRecord> {
         None
     }
 
     fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
         self.emit.value(u, v, p)
     }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.emit.value(rec.u, rec.v, &rec.p)
+    }
+}
+
+/// Phase function for participating media that scatters uniformly in all directions.
+pub struct Isotropic {
+    albedo: SharedTexture,
+}
+
+impl Isotropic {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(albedo: SharedTexture) -> SharedMaterial {
+        Arc::new(Isotropic { albedo })
+    }
+}
+
+impl Material for Isotropic {
+    fn sample(
+        &self,
+        rec: &HitRecord,
+        _wo: &Vec3,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        Some(ScatterRecord {
+            direction: sample_unit_vector(sampler.get_2d()),
+            pdf: 1.0 / (4.0 * PI),
+            weight: self.albedo.value(rec.u, rec.v, &rec.p),
+            is_specular: false,
+        })
+    }
+
+    fn eval(&self, rec: &HitRecord, _wi: &Vec3, _wo: &Vec3) -> Color {
+        self.albedo.value(rec.u, rec.v, &rec.p) / (4.0 * PI)
+    }
+
+    fn pdf(&self, _rec: &HitRecord, _wi: &Vec3, _wo: &Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(rec.u, rec.v, &rec.p)
+    }
+
+    fn is_volumetric(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+    use crate::sampler::SamplerType;
+    use crate::texture::SolidColor;
+
+    /// Checks that the directions `material` samples come with the density `pdf` gives them
+    /// and the weight `eval` divided by it, hit from outside and from inside the surface.
+    fn assert_sample_matches_eval_and_pdf(material: SharedMaterial) {
+        let wo = Vec3::new(0.3, 1.0, -0.2).normalized();
+        for outward in [1.0, -1.0] {
+            let ray = Ray::new(Point3::zero() + wo, -wo, 0.0);
+            let normal = Vec3::new(0.0, outward, 0.0);
+            let rec = HitRecord::new(
+                Point3::zero(),
+                1.0,
+                0.5,
+                0.5,
+                &ray,
+                &normal,
+                material.clone(),
+            );
+            let mut sampler = SamplerType::Independent.create(0, 64);
+            for i in 0..64 {
+                sampler.start_pixel_sample(0, i);
+                let s = match rec.material.sample(&rec, &wo, sampler.as_mut()) {
+                    Some(s) => s,
+                    None => continue,
+                };
+                let pdf = rec.material.pdf(&rec, &s.direction, &wo);
+                let f = rec.material.eval(&rec, &s.direction, &wo);
+                assert!(!s.is_specular && (s.pdf - pdf).abs() < 1e-9 * pdf);
+                assert!((s.weight - f / pdf).mag() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sample_matches_eval_and_pdf() {
+        let albedo = SolidColor::new(Color::new(0.8, 0.5, 0.2));
+        let gold = ConductorFresnel::Complex {
+            eta: Color::new(0.143, 0.374, 1.442),
+            k: Color::new(3.983, 2.385, 1.603),
+        };
+        let materials = [
+            Lambertian::new(albedo.clone()),
+            Isotropic::new(albedo),
+            Metal::new(Color::new(0.9, 0.6, 0.3), 0.4),
+            Metal::conductor(gold, 0.2, 0.7),
+            Dielectric::rough(1.5, 0.3, 0.3),
+            Dielectric::rough(1.33, 0.1, 0.6),
+        ];
+        for material in materials {
+            assert_sample_matches_eval_and_pdf(material);
+        }
+    }
+
+    #[test]
+    fn test_principled_sample_matches_eval_and_pdf() {
+        let gray = |value: f64| SolidColor::new(Color::full(value));
+        let parameters = [
+            Principled::default(),
+            Principled {
+                metallic: gray(0.3),
+                clearcoat: gray(1.0),
+                sheen: gray(0.5),
+                transmission: gray(0.5),
+                ..Default::default()
+            },
+            // A smooth metal, the specular lobe alone
+            Principled {
+                base_color: SolidColor::new(Color::new(0.9, 0.6, 0.3)),
+                metallic: gray(1.0),
+                roughness: gray(0.0),
+                ..Default::default()
+            },
+            Principled {
+                metallic: gray(1.0),
+                roughness: gray(0.8),
+                clearcoat: gray(0.5),
+                ..Default::default()
+            },
+            // Glass, smooth and rough
+            Principled {
+                roughness: gray(0.0),
+                transmission: gray(1.0),
+                ..Default::default()
+            },
+            Principled {
+                base_color: SolidColor::new(Color::new(0.2, 0.8, 0.5)),
+                roughness: gray(0.4),
+                transmission: gray(1.0),
+                ior: 1.33,
+                ..Default::default()
+            },
+            // A clear coat over a diffuse base
+            Principled {
+                roughness: gray(1.0),
+                specular: gray(1.0),
+                clearcoat: gray(1.0),
+                ..Default::default()
+            },
+        ];
+        for principled in parameters {
+            assert_sample_matches_eval_and_pdf(Arc::new(principled));
+        }
+    }
+
+    #[test]
+    fn test_principled_lobe_weights() {
+        let color = |r, g, b| SolidColor::new(Color::new(r, g, b));
+        let lobes = |principled: Principled| {
+            let ray = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+            let normal = Vec3::new(0.0, 1.0, 0.0);
+            let rec = HitRecord::new(
+                Point3::zero(),
+                1.0,
+                0.5,
+                0.5,
+                &ray,
+                &normal,
+                Lambertian::new(color(1.0, 1.0, 1.0)),
+            );
+            principled.lobes(&rec)
+        };
+
+        // Parameters from textures out of range are clamped to [0, 1]
+        let metal = lobes(Principled {
+            metallic: color(2.0, 3.0, 4.0),
+            roughness: color(-1.0, -1.0, -1.0),
+            clearcoat: color(5.0, 5.0, 5.0),
+            transmission: color(-2.0, -2.0, -2.0),
+            sheen: color(-1.0, 0.0, 0.0),
+            ..Default::default()
+        });
+        assert_eq!(metal.weights, [0.0, 1.0, 0.0, 0.25]);
+        assert_eq!(metal.roughness, PRINCIPLED_MIN_ROUGHNESS);
+        assert_eq!(metal.sheen, 0.0);
+        assert_eq!(metal.probabilities(), [0.0, 0.8, 0.0, 0.2]);
+
+        let glass = lobes(Principled {
+            metallic: color(-1.0, -1.0, -1.0),
+            transmission: color(3.0, 3.0, 3.0),
+            ..Default::default()
+        });
+        assert_eq!(glass.weights, [0.0, 0.0, 1.0, 0.0]);
+        assert_eq!(glass.probabilities(), [0.0, 0.0, 1.0, 0.0]);
+
+        // Scalar parameters are the mean of the channels, clamped once
+        let mixed = lobes(Principled {
+            metallic: color(1.5, 0.5, -0.5),
+            transmission: color(1.0, 0.0, 0.0),
+            clearcoat: color(2.0, 0.0, 0.0),
+            ..Default::default()
+        });
+        let third: f64 = 1.0 / 3.0;
+        let expected = [
+            0.5 * (1.0 - third),
+            1.0 - 0.5 * third,
+            0.5 * third,
+            0.25 * (2.0 / 3.0),
+        ];
+        for (weight, expected) in mixed.weights.iter().zip(expected) {
+            assert!((weight - expected).abs() < 1e-12);
+        }
+        let total: f64 = mixed.probabilities().iter().sum();
+        assert!((total - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_fresnel_dielectric() {
+        // At normal incidence, ((eta - 1) / (eta + 1))^2, and total internal reflection past
+        // the critical angle
+        let f0 = (0.5f64 / 2.5).powi(2);
+        assert!((fresnel_dielectric(1.0, 1.5) - f0).abs() < 1e-12);
+        assert!((fresnel_dielectric(1.0, 1.0 / 1.5) - f0).abs() < 1e-12);
+        assert!((fresnel_dielectric(0.0, 1.5) - 1.0).abs() < 1e-12);
+        assert_eq!(fresnel_dielectric(0.5, 1.0 / 1.5), 1.0);
+    }
+
+    #[test]
+    fn test_absorption_for() {
+        let transmittance = Color::new(0.9, 0.5, 0.1);
+        let absorption = Dielectric::absorption_for(transmittance, 2.0);
+        let glass = Dielectric::tinted(1.5, 0.0, 0.0, absorption);
+        assert!((glass.transmittance(2.0) - transmittance).mag() < 1e-12);
+        assert!((glass.transmittance(4.0) - transmittance * transmittance).mag() < 1e-12);
+    }
+
+    #[test]
+    fn test_fresnel_conductor() {
+        // At normal incidence, ((eta - 1)^2 + k^2) / ((eta + 1)^2 + k^2)
+        let (eta, k) = (0.2, 3.0);
+        let f0 = ((eta - 1.0f64).powi(2) + k * k) / ((eta + 1.0f64).powi(2) + k * k);
+        assert!((fresnel_conductor(1.0, eta, k) - f0).abs() < 1e-12);
+        assert!((fresnel_conductor(0.0, eta, k) - 1.0).abs() < 1e-12);
+        assert!(fresnel_conductor(1.0, 1.0, 0.0).abs() < 1e-12);
+    }
 }
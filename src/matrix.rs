@@ -0,0 +1,167 @@
+use std::ops::Mul;
+
+use crate::util::Point3;
+use crate::vec3::*;
+
+/// A 4x4 row-major matrix for affine transforms of points, vectors and normals.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Mat4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, other: Mat4) -> Mat4 {
+        Mat4 {
+            m: std::array::from_fn(|i| {
+                std::array::from_fn(|j| (0..4).map(|k| self.m[i][k] * other.m[k][j]).sum())
+            }),
+        }
+    }
+}
+
+impl Mat4 {
+    pub fn new(m: [[f64; 4]; 4]) -> Mat4 {
+        Mat4 { m }
+    }
+
+    pub fn identity() -> Mat4 {
+        Self::scale(&Vec3::one())
+    }
+
+    pub fn translate(offset: &Vec3) -> Mat4 {
+        Mat4::new([
+            [1.0, 0.0, 0.0, offset.x],
+            [0.0, 1.0, 0.0, offset.y],
+            [0.0, 0.0, 1.0, offset.z],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn scale(s: &Vec3) -> Mat4 {
+        Mat4::new([
+            [s.x, 0.0, 0.0, 0.0],
+            [0.0, s.y, 0.0, 0.0],
+            [0.0, 0.0, s.z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// Rotation by `degrees` counter-clockwise around `axis` (Rodrigues' formula).
+    pub fn rotate(axis: &Vec3, degrees: f64) -> Mat4 {
+        let a = axis.normalized();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let t = 1.0 - cos;
+
+        Mat4::new([
+            [
+                t * a.x * a.x + cos,
+                t * a.x * a.y - sin * a.z,
+                t * a.x * a.z + sin * a.y,
+                0.0,
+            ],
+            [
+                t * a.x * a.y + sin * a.z,
+                t * a.y * a.y + cos,
+                t * a.y * a.z - sin * a.x,
+                0.0,
+            ],
+            [
+                t * a.x * a.z - sin * a.y,
+                t * a.y * a.z + sin * a.x,
+                t * a.z * a.z + cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        Mat4 {
+            m: std::array::from_fn(|i| std::array::from_fn(|j| self.m[j][i])),
+        }
+    }
+
+    /// Inverts the matrix with Gauss-Jordan elimination, returning `None` if it is singular.
+    pub fn inverse(&self) -> Option<Mat4> {
+        let mut a = self.m;
+        let mut inv = Mat4::identity().m;
+
+        for col in 0..4 {
+            // Partial pivoting for numerical stability
+            let pivot = (col..4)
+                .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+                .unwrap();
+            if a[pivot][col].abs() < 1.0e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = 1.0 / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+
+            for row in 0..4 {
+                if row != col {
+                    let factor = a[row][col];
+                    for j in 0..4 {
+                        a[row][j] -= factor * a[col][j];
+                        inv[row][j] -= factor * inv[col][j];
+                    }
+                }
+            }
+        }
+
+        Some(Mat4 { m: inv })
+    }
+
+    pub fn transform_point(&self, p: &Point3) -> Point3 {
+        let m = &self.m;
+        let x = m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3];
+        let y = m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3];
+        let z = m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3];
+        let w = m[3][0] * p.x + m[3][1] * p.y + m[3][2] * p.z + m[3][3];
+        Point3::new(x, y, z) / w
+    }
+
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapprox_eq(a: &Mat4, b: &Mat4) -> bool {
+        (0..4).all(|i| (0..4).all(|j| (a.m[i][j] - b.m[i][j]).abs() < 1.0e-9))
+    }
+
+    #[test]
+    fn test_inverse() {
+        let m = Mat4::translate(&Vec3::new(1.0, -2.0, 3.0))
+            * Mat4::rotate(&Vec3::new(1.0, 1.0, 0.0), 33.0)
+            * Mat4::scale(&Vec3::new(2.0, 0.5, -1.0));
+        let inv = m.inverse().unwrap();
+
+        assert!(mapprox_eq(&(m * inv), &Mat4::identity()));
+        assert!(mapprox_eq(&(inv * m), &Mat4::identity()));
+        assert!(Mat4::scale(&Vec3::new(1.0, 0.0, 1.0)).inverse().is_none());
+    }
+
+    #[test]
+    fn test_rotate() {
+        let r = Mat4::rotate(&Vec3::new(0.0, 1.0, 0.0), 90.0);
+        let v = r.transform_vector(&Vec3::new(1.0, 0.0, 0.0));
+        assert!((v - Vec3::new(0.0, 0.0, -1.0)).mag() < 1.0e-9);
+    }
+}
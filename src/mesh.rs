@@ -0,0 +1,210 @@
+use crate::aabb::AABB;
+use crate::bvh::{BvhSplit, LinearBvh};
+use crate::hittable::{HitRecord, Hittable, SharedHittable};
+use crate::material::SharedMaterial;
+use crate::ray::Ray;
+use crate::triangle;
+use crate::util::{Point3, Time};
+use crate::vec3::Vec3;
+use std::sync::Arc;
+
+/// Vertex attributes shared by all the triangles of a mesh.
+#[derive(Default)]
+pub struct MeshData {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+}
+
+/// One corner of a face, indexing into the attribute arrays of [`MeshData`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MeshVertex {
+    pub position: usize,
+    pub uv: Option<usize>,
+    pub normal: Option<usize>,
+}
+
+pub struct MeshFace {
+    pub vertices: [MeshVertex; 3],
+    pub material: SharedMaterial,
+}
+
+struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    face: MeshFace,
+}
+
+impl MeshTriangle {
+    fn positions(&self) -> [Point3; 3] {
+        let v = &self.face.vertices;
+        [
+            self.mesh.positions[v[0].position],
+            self.mesh.positions[v[1].position],
+            self.mesh.positions[v[2].position],
+        ]
+    }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let positions = self.positions();
+        let hit = triangle::intersect(&positions, r, t_min, t_max)?;
+
+        let v = &self.face.vertices;
+        let normals = match (v[0].normal, v[1].normal, v[2].normal) {
+            (Some(n0), Some(n1), Some(n2)) => Some([
+                self.mesh.normals[n0],
+                self.mesh.normals[n1],
+                self.mesh.normals[n2],
+            ]),
+            _ => None,
+        };
+        let uvs = match (v[0].uv, v[1].uv, v[2].uv) {
+            (Some(t0), Some(t1), Some(t2)) => {
+                [self.mesh.uvs[t0], self.mesh.uvs[t1], self.mesh.uvs[t2]]
+            }
+            _ => [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)],
+        };
+
+        Some(triangle::hit_record(
+            &positions,
+            normals.as_ref(),
+            &uvs,
+            r,
+            hit,
+            self.face.material.clone(),
+        ))
+    }
+
+    fn bounding_box(&self, _t0: Time, _t1: Time) -> Option<AABB> {
+        Some(triangle::bounding_box(&self.positions()))
+    }
+}
+
+/// A triangle mesh whose faces share a single set of vertex attributes.
+pub struct TriangleMesh {
+    triangles: SharedHittable,
+}
+
+impl TriangleMesh {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(mesh: MeshData, faces: Vec<MeshFace>) -> SharedHittable {
+        let mesh = Arc::new(mesh);
+        let triangles = faces
+            .into_iter()
+            .map(|face| -> SharedHittable {
+                Box::new(MeshTriangle {
+                    mesh: mesh.clone(),
+                    face,
+                })
+            })
+            .collect();
+
+        Box::new(TriangleMesh {
+            triangles: LinearBvh::from_objects(0.0, 0.0, triangles, BvhSplit::Sah),
+        })
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.triangles.hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: Time, t1: Time) -> Option<AABB> {
+        self.triangles.bounding_box(t0, t1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::texture::SolidColor;
+    use crate::util::Color;
+
+    /// A 2x1 quad in the z = 0 plane, split into a face with a shared, tilted vertex normal
+    /// and texture coordinates and a face with neither.
+    fn quad() -> SharedHittable {
+        let mesh = MeshData {
+            positions: vec![
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(2.0, 0.0, 0.0),
+                Point3::new(2.0, 1.0, 0.0),
+                Point3::new(0.0, 1.0, 0.0),
+            ],
+            normals: vec![Vec3::new(0.0, 1.0, 1.0).normalized()],
+            uvs: vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)],
+        };
+        let material = Lambertian::new(SolidColor::new(Color::one()));
+        let vertex = |position, uv, normal| MeshVertex {
+            position,
+            uv,
+            normal,
+        };
+        let faces = vec![
+            MeshFace {
+                vertices: [
+                    vertex(0, Some(0), Some(0)),
+                    vertex(1, Some(1), Some(0)),
+                    vertex(2, Some(2), Some(0)),
+                ],
+                material: material.clone(),
+            },
+            MeshFace {
+                vertices: [
+                    vertex(0, None, None),
+                    vertex(2, None, None),
+                    vertex(3, None, None),
+                ],
+                material,
+            },
+        ];
+        TriangleMesh::new(mesh, faces)
+    }
+
+    fn vertical(x: f64, y: f64, dz: f64) -> Ray {
+        Ray::new(Point3::new(x, y, -2.0 * dz), Vec3::new(0.0, 0.0, dz), 0.0)
+    }
+
+    #[test]
+    fn test_hit() {
+        let mesh = quad();
+
+        // The first face uses its vertex normal and texture coordinates
+        let rec = mesh
+            .hit(&vertical(1.5, 0.25, -1.0), 0.001, f64::INFINITY)
+            .unwrap();
+        assert!((rec.t - 2.0).abs() < 1e-12);
+        assert!((rec.p - Point3::new(1.5, 0.25, 0.0)).mag() < 1e-12);
+        assert!((rec.normal - Vec3::new(0.0, 1.0, 1.0).normalized()).mag() < 1e-12);
+        assert!(rec.front_face);
+        assert!((rec.u - 0.75).abs() < 1e-12 && (rec.v - 0.25).abs() < 1e-12);
+
+        // The second falls back to the geometric normal and barycentric coordinates
+        let rec = mesh
+            .hit(&vertical(0.5, 0.75, -1.0), 0.001, f64::INFINITY)
+            .unwrap();
+        assert!((rec.normal - Vec3::new(0.0, 0.0, 1.0)).mag() < 1e-12);
+        assert!((rec.u - 0.25).abs() < 1e-12 && (rec.v - 0.5).abs() < 1e-12);
+
+        let rec = mesh
+            .hit(&vertical(0.5, 0.75, 1.0), 0.001, f64::INFINITY)
+            .unwrap();
+        assert!(!rec.front_face);
+        assert!((rec.normal - Vec3::new(0.0, 0.0, -1.0)).mag() < 1e-12);
+
+        assert!(mesh
+            .hit(&vertical(2.5, 0.5, -1.0), 0.001, f64::INFINITY)
+            .is_none());
+        assert!(mesh.hit(&vertical(1.5, 0.25, -1.0), 0.001, 1.5).is_none());
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        let bbox = quad().bounding_box(0.0, 1.0).unwrap();
+        assert_eq!((bbox.min.x, bbox.min.y), (0.0, 0.0));
+        assert_eq!((bbox.max.x, bbox.max.y), (2.0, 1.0));
+        assert!(bbox.min.z < 0.0 && bbox.max.z > 0.0);
+    }
+}
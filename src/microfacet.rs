@@ -0,0 +1,172 @@
+use crate::vec3::{cross, dot, orthonormal_basis, Vec3};
+use std::f64::consts::PI;
+
+/// Orthonormal shading frame around a surface normal, in which the normal is +z.
+#[derive(Debug, Copy, Clone)]
+pub struct Frame {
+    s: Vec3,
+    t: Vec3,
+    n: Vec3,
+}
+
+impl Frame {
+    pub fn new(n: &Vec3) -> Frame {
+        let (s, t) = orthonormal_basis(n);
+        Frame { s, t, n: *n }
+    }
+
+    pub fn to_local(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(dot(v, &self.s), dot(v, &self.t), dot(v, &self.n))
+    }
+
+    pub fn to_world(&self, v: &Vec3) -> Vec3 {
+        v.x * self.s + v.y * self.t + v.z * self.n
+    }
+}
+
+/// Below this, a distribution is treated as perfectly smooth.
+const SMOOTH_ALPHA: f64 = 1.0e-3;
+
+/// The GGX (Trowbridge-Reitz) distribution of microfacet normals, with separate roughness
+/// along the two tangents of a shading frame. Directions are in the local frame, where the
+/// macro surface normal is +z.
+#[derive(Debug, Copy, Clone)]
+pub struct Ggx {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl Ggx {
+    /// Maps the perceptually linear `roughness` in [0, 1] along each tangent to the
+    /// distribution's alpha, its square.
+    pub fn from_roughness(roughness_x: f64, roughness_y: f64) -> Ggx {
+        Ggx {
+            alpha_x: roughness_x.clamp(0.0, 1.0).powi(2),
+            alpha_y: roughness_y.clamp(0.0, 1.0).powi(2),
+        }
+    }
+
+    /// Whether the surface is smooth enough to be treated as a perfect mirror or refractor.
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < SMOOTH_ALPHA
+    }
+
+    /// Density of microfacet normals `wm`, per unit of projected area.
+    pub fn d(&self, wm: &Vec3) -> f64 {
+        if wm.z <= 0.0 {
+            return 0.0;
+        }
+        let (x, y) = (wm.x / self.alpha_x, wm.y / self.alpha_y);
+        let s = x * x + y * y + wm.z * wm.z;
+        1.0 / (PI * self.alpha_x * self.alpha_y * s * s)
+    }
+
+    fn lambda(&self, w: &Vec3) -> f64 {
+        if w.z == 0.0 {
+            return f64::INFINITY;
+        }
+        let a2 = (self.alpha_x * w.x).powi(2) + (self.alpha_y * w.y).powi(2);
+        ((1.0 + a2 / (w.z * w.z)).sqrt() - 1.0) / 2.0
+    }
+
+    /// Fraction of the microfacets facing `w` that are visible from it (Smith).
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Fraction of microfacets visible from both `wo` and `wi`, height-correlated.
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Density of the microfacet normals seen from `w`, which `sample_visible_normal` follows.
+    pub fn visible_pdf(&self, w: &Vec3, wm: &Vec3) -> f64 {
+        if w.z == 0.0 {
+            return 0.0;
+        }
+        self.g1(w) / w.z.abs() * self.d(wm) * dot(w, wm).max(0.0)
+    }
+
+    /// Samples a microfacet normal in proportion to how much of it is visible from `w`
+    /// (Heitz, "Sampling the GGX Distribution of Visible Normals").
+    pub fn sample_visible_normal(&self, w: &Vec3, (u1, u2): (f64, f64)) -> Vec3 {
+        // Stretch the view direction to the configuration where the distribution is a
+        // hemisphere of unit roughness
+        let mut wh = Vec3::new(self.alpha_x * w.x, self.alpha_y * w.y, w.z).normalized();
+        if wh.z < 0.0 {
+            wh = -wh;
+        }
+        let t1 = if wh.z < 0.99999 {
+            cross(&Vec3::new(0.0, 0.0, 1.0), &wh).normalized()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = cross(&wh, &t1);
+
+        // Uniform point on the disk, warped to the projected area of the visible hemisphere
+        let (r, phi) = (u1.sqrt(), 2.0 * PI * u2);
+        let (px, mut py) = (r * phi.cos(), r * phi.sin());
+        let h = (1.0 - px * px).sqrt();
+        let s = (1.0 + wh.z) / 2.0;
+        py = (1.0 - s) * h + s * py;
+        let pz = (1.0 - px * px - py * py).max(0.0).sqrt();
+
+        let nh = px * t1 + py * t2 + pz * wh;
+        Vec3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(1.0e-6)).normalized()
+    }
+}
+
+/// Reflection of `w` about the normal `n`, both pointing away from the surface.
+pub fn reflect_about(w: &Vec3, n: &Vec3) -> Vec3 {
+    2.0 * dot(w, n) * n - w
+}
+
+/// Refraction of `w` through the normal `n`, both pointing away from the surface on the same
+/// side, where `eta` is the index of refraction on the far side relative to the near side.
+/// Returns `None` on total internal reflection.
+pub fn refract_about(w: &Vec3, n: &Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = dot(w, n);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-w / eta + (cos_i / eta - cos_t) * n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::SamplerType;
+    use crate::vec3::sample_unit_vector;
+
+    #[test]
+    fn test_visible_normals_match_pdf() {
+        // Integrating over uniformly sampled normals, the visible normal density integrates to
+        // one and gives the same average of a test function as the sampled normals
+        let ggx = Ggx::from_roughness(0.5, 0.8);
+        let w = Vec3::new(0.4, -0.3, 0.6).normalized();
+        let g = |wm: &Vec3| wm.x + 2.0 * wm.y * wm.y + wm.z;
+        let n = 1 << 15;
+        let mut sampler = SamplerType::Sobol.create(3, n);
+        let (mut total, mut expected, mut sampled) = (0.0, 0.0, 0.0);
+        for i in 0..n {
+            sampler.start_pixel_sample(0, i);
+            let mut wm = sample_unit_vector(sampler.get_2d());
+            wm.z = wm.z.abs();
+            let pdf = ggx.visible_pdf(&w, &wm) * 2.0 * PI;
+            total += pdf;
+            expected += g(&wm) * pdf;
+
+            sampled += g(&ggx.sample_visible_normal(&w, sampler.get_2d()));
+        }
+        let n = n as f64;
+        assert!((total / n - 1.0).abs() < 0.02, "{}", total / n);
+        assert!(
+            (expected / n - sampled / n).abs() < 0.02,
+            "{} {}",
+            expected,
+            sampled
+        );
+    }
+}
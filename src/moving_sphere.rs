@@ -16,6 +16,7 @@ pub struct MovingSphere {
 }
 
 impl MovingSphere {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(
         center0: Point3,
         center1: Point3,
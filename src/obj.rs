@@ -0,0 +1,484 @@
+use crate::hittable::SharedHittable;
+use crate::material::*;
+use crate::mesh::{MeshData, MeshFace, MeshVertex, TriangleMesh};
+use crate::texture::{Image, SharedTexture, SolidColor};
+use crate::util::*;
+use crate::vec3::Vec3;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+impl Error for ObjError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ObjError::Io { source, .. } => Some(source),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+/// Loads a Wavefront OBJ file as a single triangle mesh.
+///
+/// Polygons are triangulated into fans. Materials come from the `mtllib` files the OBJ
+/// references; faces before any `usemtl` use `default_material`.
+pub fn load_obj(
+    path: impl AsRef<Path>,
+    default_material: SharedMaterial,
+) -> Result<SharedHittable, ObjError> {
+    let path = path.as_ref();
+    let (mesh, faces) = parse_obj(open(path)?, path, default_material)?;
+    Ok(TriangleMesh::new(mesh, faces))
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, ObjError> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|source| ObjError::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+/// Tracks the current file and line so errors can point at the bad entry.
+struct Location<'a> {
+    path: &'a Path,
+    line: usize,
+}
+
+impl<'a> Location<'a> {
+    fn error(&self, message: impl Into<String>) -> ObjError {
+        ObjError::Parse {
+            path: self.path.to_path_buf(),
+            line: self.line,
+            message: message.into(),
+        }
+    }
+
+    fn floats<const N: usize>(
+        &self,
+        keyword: &str,
+        fields: &[&str],
+        required: usize,
+    ) -> Result<[f64; N], ObjError> {
+        if fields.len() < required || fields.len() > N {
+            return Err(self.error(format!(
+                "'{}' expects {} values, found {}",
+                keyword,
+                if required == N {
+                    N.to_string()
+                } else {
+                    format!("{} to {}", required, N)
+                },
+                fields.len()
+            )));
+        }
+
+        let mut values = [0.0; N];
+        for (value, field) in values.iter_mut().zip(fields) {
+            *value = field
+                .parse()
+                .map_err(|_| self.error(format!("invalid number '{}' in '{}'", field, keyword)))?;
+        }
+        Ok(values)
+    }
+
+    fn color(&self, keyword: &str, fields: &[&str]) -> Result<Color, ObjError> {
+        let [r, g, b] = self.floats(keyword, fields, 3)?;
+        Ok(Color::new(r, g, b))
+    }
+
+    /// Resolves a 1-based (or negative, relative) OBJ index into an array of length `len`.
+    fn index(&self, field: &str, kind: &str, len: usize) -> Result<usize, ObjError> {
+        let idx: i64 = field
+            .parse()
+            .map_err(|_| self.error(format!("invalid {} index '{}'", kind, field)))?;
+
+        let resolved = if idx > 0 { idx - 1 } else { len as i64 + idx };
+
+        if idx == 0 || resolved < 0 || resolved >= len as i64 {
+            return Err(self.error(format!(
+                "{} index {} out of range ({} defined)",
+                kind, idx, len
+            )));
+        }
+        Ok(resolved as usize)
+    }
+}
+
+fn lines<'a, R: BufRead + 'a>(
+    reader: R,
+    path: &'a Path,
+) -> impl Iterator<Item = Result<(usize, String), ObjError>> + 'a {
+    reader.lines().enumerate().map(move |(i, line)| {
+        line.map(|l| (i + 1, l)).map_err(|source| ObjError::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+    })
+}
+
+fn parse_obj<R: BufRead>(
+    reader: R,
+    path: &Path,
+    default_material: SharedMaterial,
+) -> Result<(MeshData, Vec<MeshFace>), ObjError> {
+    let mut mesh = MeshData::default();
+    let mut faces = Vec::new();
+    let mut materials = HashMap::new();
+    let mut material = default_material;
+
+    for line in lines(reader, path) {
+        let (line, text) = line?;
+        let loc = Location { path, line };
+
+        let mut fields = text.split_whitespace();
+        let keyword = match fields.next() {
+            Some(k) if !k.starts_with('#') => k,
+            _ => continue,
+        };
+        let fields: Vec<&str> = fields.collect();
+
+        match keyword {
+            "v" => {
+                // An optional fourth (w) component is accepted and ignored
+                let [x, y, z, _] = loc.floats(keyword, &fields, 3)?;
+                mesh.positions.push(Point3::new(x, y, z));
+            }
+            "vt" => {
+                let [u, v, _] = loc.floats(keyword, &fields, 1)?;
+                mesh.uvs.push((u, v));
+            }
+            "vn" => {
+                let [x, y, z] = loc.floats(keyword, &fields, 3)?;
+                let normal = Vec3::new(x, y, z);
+                if normal.mag_squared() == 0.0 {
+                    return Err(loc.error("'vn' needs a non-zero normal"));
+                }
+                mesh.normals.push(normal.normalized());
+            }
+            "f" => {
+                if fields.len() < 3 {
+                    return Err(loc.error(format!(
+                        "face needs at least 3 vertices, found {}",
+                        fields.len()
+                    )));
+                }
+
+                let vertices = fields
+                    .iter()
+                    .map(|field| parse_face_vertex(&loc, field, &mesh))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                for i in 1..vertices.len() - 1 {
+                    faces.push(MeshFace {
+                        vertices: [vertices[0], vertices[i], vertices[i + 1]],
+                        material: material.clone(),
+                    });
+                }
+            }
+            "mtllib" => {
+                if fields.is_empty() {
+                    return Err(loc.error("'mtllib' expects a file name"));
+                }
+                let dir = path.parent().unwrap_or_else(|| Path::new(""));
+                for name in fields {
+                    let mtl_path = dir.join(name);
+                    materials.extend(parse_mtl(open(&mtl_path)?, &mtl_path)?);
+                }
+            }
+            "usemtl" => {
+                let name = fields.join(" ");
+                material = materials
+                    .get(&name)
+                    .cloned()
+                    .ok_or_else(|| loc.error(format!("unknown material '{}'", name)))?;
+            }
+            // Grouping, smoothing groups, lines, points and free-form geometry do not
+            // affect the mesh
+            _ => {}
+        }
+    }
+
+    Ok((mesh, faces))
+}
+
+fn parse_face_vertex(loc: &Location, field: &str, mesh: &MeshData) -> Result<MeshVertex, ObjError> {
+    let mut parts = field.split('/');
+
+    let position = loc.index(parts.next().unwrap_or(""), "vertex", mesh.positions.len())?;
+    let uv = match parts.next() {
+        Some("") | None => None,
+        Some(s) => Some(loc.index(s, "texture coordinate", mesh.uvs.len())?),
+    };
+    let normal = match parts.next() {
+        Some("") | None => None,
+        Some(s) => Some(loc.index(s, "normal", mesh.normals.len())?),
+    };
+
+    if parts.next().is_some() {
+        return Err(loc.error(format!("invalid face vertex '{}'", field)));
+    }
+
+    Ok(MeshVertex {
+        position,
+        uv,
+        normal,
+    })
+}
+
+struct MtlSpec {
+    kd: Color,
+    ks: Color,
+    ke: Color,
+    ns: f64,
+    ni: f64,
+    dissolve: f64,
+    illum: Option<u32>,
+    map_kd: Option<SharedTexture>,
+}
+
+impl Default for MtlSpec {
+    fn default() -> MtlSpec {
+        MtlSpec {
+            kd: Color::full(0.8),
+            ks: Color::zero(),
+            ke: Color::zero(),
+            ns: 0.0,
+            ni: 1.0,
+            dissolve: 1.0,
+            illum: None,
+            map_kd: None,
+        }
+    }
+}
+
+impl MtlSpec {
+    /// Maps the MTL parameters onto the closest of the available materials: emissive
+    /// materials become lights, transparent ones glass, ones that are more specular than
+    /// diffuse metal (with the Phong exponent converted to fuzz), and everything else diffuse.
+    fn to_material(&self) -> SharedMaterial {
+        if self.ke.max_element() > 0.0 {
+            DiffuseLight::new(SolidColor::new(self.ke))
+        } else if self.dissolve < 1.0 || matches!(self.illum, Some(4) | Some(6) | Some(7)) {
+            Dielectric::new(self.ni)
+        } else if self.map_kd.is_none() && self.ks.max_element() > self.kd.max_element() {
+            let fuzz = (2.0 / (self.ns + 2.0)).sqrt();
+            Metal::new(self.ks, fuzz)
+        } else {
+            let albedo = self
+                .map_kd
+                .clone()
+                .unwrap_or_else(|| SolidColor::new(self.kd));
+            Lambertian::new(albedo)
+        }
+    }
+}
+
+fn parse_mtl<R: BufRead>(
+    reader: R,
+    path: &Path,
+) -> Result<HashMap<String, SharedMaterial>, ObjError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlSpec)> = None;
+
+    for line in lines(reader, path) {
+        let (line, text) = line?;
+        let loc = Location { path, line };
+
+        let mut fields = text.split_whitespace();
+        let keyword = match fields.next() {
+            Some(k) if !k.starts_with('#') => k,
+            _ => continue,
+        };
+        let fields: Vec<&str> = fields.collect();
+
+        if keyword == "newmtl" {
+            if fields.is_empty() {
+                return Err(loc.error("'newmtl' expects a material name"));
+            }
+            if let Some((name, spec)) = current.take() {
+                materials.insert(name, spec.to_material());
+            }
+            current = Some((fields.join(" "), MtlSpec::default()));
+            continue;
+        }
+
+        let spec = match current.as_mut() {
+            Some((_, spec)) => spec,
+            None => return Err(loc.error(format!("'{}' before any 'newmtl'", keyword))),
+        };
+
+        match keyword {
+            "Kd" => spec.kd = loc.color(keyword, &fields)?,
+            "Ks" => spec.ks = loc.color(keyword, &fields)?,
+            "Ke" => spec.ke = loc.color(keyword, &fields)?,
+            "Ns" => {
+                let ns = loc.floats::<1>(keyword, &fields, 1)?[0];
+                if ns.is_nan() || ns < 0.0 {
+                    return Err(loc.error(format!("'Ns' must not be negative, found {}", ns)));
+                }
+                spec.ns = ns;
+            }
+            "Ni" => {
+                let ni = loc.floats::<1>(keyword, &fields, 1)?[0];
+                if ni.is_nan() || ni <= 0.0 {
+                    return Err(loc.error(format!("'Ni' must be positive, found {}", ni)));
+                }
+                spec.ni = ni;
+            }
+            "d" => spec.dissolve = loc.floats::<1>(keyword, &fields, 1)?[0],
+            "Tr" => spec.dissolve = 1.0 - loc.floats::<1>(keyword, &fields, 1)?[0],
+            "illum" => {
+                let illum = fields
+                    .first()
+                    .and_then(|s| s.parse().ok())
+                    .ok_or_else(|| loc.error("'illum' expects an integer"))?;
+                spec.illum = Some(illum);
+            }
+            "map_Kd" => {
+                // Texture options come before the file name, which is always last
+                let name = fields
+                    .last()
+                    .ok_or_else(|| loc.error("'map_Kd' expects a file name"))?;
+                let dir = path.parent().unwrap_or_else(|| Path::new(""));
+                let texture_path = dir.join(name);
+                let texture = Image::open(&texture_path).map_err(|err| {
+                    loc.error(format!(
+                        "could not load texture '{}': {}",
+                        texture_path.display(),
+                        err
+                    ))
+                })?;
+                spec.map_kd = Some(texture);
+            }
+            // Ambient color, the other texture maps and extensions have no equivalent here
+            _ => {}
+        }
+    }
+
+    if let Some((name, spec)) = current {
+        materials.insert(name, spec.to_material());
+    }
+
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn parse(src: &str) -> Result<(MeshData, Vec<MeshFace>), ObjError> {
+        let default_material = Lambertian::new(SolidColor::new(Color::one()));
+        parse_obj(Cursor::new(src), Path::new("test.obj"), default_material)
+    }
+
+    fn error_line(err: ObjError) -> usize {
+        match err {
+            ObjError::Parse { line, .. } => line,
+            ObjError::Io { .. } => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn test_triangulates_polygons() {
+        let (mesh, faces) = parse(
+            "# quad\n\
+             v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+             vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+             vn 0 0 2\n\
+             f 1/1/1 2/2/1 3/3/1 4/4/1\n",
+        )
+        .unwrap();
+
+        assert_eq!(mesh.positions.len(), 4);
+        assert!((mesh.normals[0].mag() - 1.0).abs() < 1e-12);
+        assert_eq!(faces.len(), 2);
+
+        let positions: Vec<_> = faces[1].vertices.iter().map(|v| v.position).collect();
+        assert_eq!(positions, vec![0, 2, 3]);
+        assert_eq!(faces[1].vertices[2].uv, Some(3));
+        assert_eq!(faces[1].vertices[2].normal, Some(0));
+    }
+
+    #[test]
+    fn test_negative_and_partial_indices() {
+        let (_, faces) =
+            parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf -3//1 -2//1 -1//1\n").unwrap();
+
+        let v = faces[0].vertices;
+        assert_eq!((v[0].position, v[1].position, v[2].position), (0, 1, 2));
+        assert_eq!(v[0].uv, None);
+        assert_eq!(v[0].normal, Some(0));
+    }
+
+    #[test]
+    fn test_errors_report_line() {
+        assert_eq!(error_line(parse("v 0 0 0\nv 1 0\n").err().unwrap()), 2);
+        assert_eq!(error_line(parse("v 0 0 x\n").err().unwrap()), 1);
+        assert_eq!(
+            error_line(parse("v 0 0 0\nv 1 0 0\n\nf 1 2 3\n").err().unwrap()),
+            4
+        );
+        assert_eq!(error_line(parse("v 0 0 0\nf 1 1\n").err().unwrap()), 2);
+        assert_eq!(error_line(parse("usemtl missing\n").err().unwrap()), 1);
+    }
+
+    #[test]
+    fn test_zero_normal_is_an_error() {
+        let err = parse("vn 0 0 1\nvn 0 0 0\n").err().unwrap();
+        assert_eq!(error_line(err), 2);
+        let (mesh, _) = parse("vn 0 0 2\n").unwrap();
+        assert!((mesh.normals[0] - Vec3::new(0.0, 0.0, 1.0)).mag() < 1e-12);
+    }
+
+    #[test]
+    fn test_mtl_errors_report_line() {
+        let err = parse_mtl(Cursor::new("newmtl a\nKd 1 0\n"), Path::new("test.mtl"));
+        assert_eq!(error_line(err.err().unwrap()), 2);
+
+        let err = parse_mtl(Cursor::new("Kd 1 0 0\n"), Path::new("test.mtl"));
+        assert_eq!(error_line(err.err().unwrap()), 1);
+
+        let err = parse_mtl(Cursor::new("newmtl a\nNi 0\n"), Path::new("test.mtl"));
+        assert_eq!(error_line(err.err().unwrap()), 2);
+
+        let err = parse_mtl(
+            Cursor::new("newmtl a\nd 0.5\nNs -1\n"),
+            Path::new("test.mtl"),
+        );
+        assert_eq!(error_line(err.err().unwrap()), 3);
+
+        let src = "newmtl a\nNs 0\nNi 1.5\n";
+        assert!(parse_mtl(Cursor::new(src), Path::new("test.mtl")).is_ok());
+    }
+}
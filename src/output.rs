@@ -0,0 +1,85 @@
+use crate::render::Framebuffer;
+use crate::util::Color;
+use image::codecs::hdr::HdrEncoder;
+use image::{ImageFormat, ImageResult, Rgb, RgbImage};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    /// Binary (P6) PPM
+    Ppm,
+    /// Radiance HDR, which keeps the unclamped linear radiance
+    Hdr,
+}
+
+impl OutputFormat {
+    pub fn from_path(path: &Path) -> Option<OutputFormat> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "png" => Some(OutputFormat::Png),
+            "jpg" | "jpeg" => Some(OutputFormat::Jpeg),
+            "ppm" => Some(OutputFormat::Ppm),
+            "hdr" => Some(OutputFormat::Hdr),
+            _ => None,
+        }
+    }
+}
+
+/// Converts linear radiance to 8-bit color, gamma-correcting for gamma=2.0.
+pub fn to_rgb8(color: &Color) -> [u8; 3] {
+    let sc = color.sqrt();
+    let (fr, fg, fb) = (256.0 * sc.clamp(0.0, 0.999)).as_tuple();
+    [fr as u8, fg as u8, fb as u8]
+}
+
+/// Writes an ASCII (P3) PPM.
+pub fn write_ppm_ascii(out: &mut impl Write, image: &Framebuffer) -> io::Result<()> {
+    writeln!(out, "P3\n{} {}\n255", image.width, image.height)?;
+    for color in &image.pixels {
+        let [r, g, b] = to_rgb8(color);
+        writeln!(out, "{} {} {}", r, g, b)?;
+    }
+    out.flush()
+}
+
+/// Writes the image to `path` in the given format.
+pub fn write_image(path: &Path, format: OutputFormat, image: &Framebuffer) -> ImageResult<()> {
+    let (width, height, pixels) = (image.width, image.height, &image.pixels[..]);
+    match format {
+        OutputFormat::Png => write_ldr(path, ImageFormat::Png, width, height, pixels),
+        OutputFormat::Jpeg => write_ldr(path, ImageFormat::Jpeg, width, height, pixels),
+        // The PNM encoder picks binary PPM from the extension
+        OutputFormat::Ppm => write_ldr(path, ImageFormat::Pnm, width, height, pixels),
+        OutputFormat::Hdr => write_hdr(path, width, height, pixels),
+    }
+}
+
+fn write_ldr(
+    path: &Path,
+    format: ImageFormat,
+    width: u32,
+    height: u32,
+    pixels: &[Color],
+) -> ImageResult<()> {
+    let mut image = RgbImage::new(width, height);
+    for (pixel, color) in image.pixels_mut().zip(pixels) {
+        *pixel = Rgb(to_rgb8(color));
+    }
+    image.save_with_format(path, format)
+}
+
+fn write_hdr(path: &Path, width: u32, height: u32, pixels: &[Color]) -> ImageResult<()> {
+    let data: Vec<Rgb<f32>> = pixels
+        .iter()
+        .map(|c| Rgb([c.x as f32, c.y as f32, c.z as f32]))
+        .collect();
+
+    let mut out = BufWriter::new(File::create(path)?);
+    HdrEncoder::new(&mut out).encode(&data, width as usize, height as usize)?;
+    out.flush()?;
+    Ok(())
+}
@@ -1,6 +1,6 @@
-use crate::util::Point3;
+use crate::util::{Point3, Rng};
 use crate::vec3::*;
-use rand::prelude::*;
+use rand::seq::SliceRandom;
 
 pub struct Perlin {
     vecs: Vec<Vec3>,
@@ -10,17 +10,18 @@ pub struct Perlin {
 }
 
 impl Perlin {
-    pub fn new() -> Perlin {
-        let mut rng = thread_rng();
+    pub fn new(rng: &mut Rng) -> Perlin {
         let size = 256;
-        let vecs = (0..size).map(|_| Vec3::random_range(-1.0, 1.0)).collect();
+        let vecs = (0..size)
+            .map(|_| Vec3::random_range(-1.0, 1.0, rng))
+            .collect();
 
         let mut permx: Vec<_> = (0..size).collect();
-        permx.shuffle(&mut rng);
+        permx.shuffle(rng);
         let mut permy: Vec<_> = (0..size).collect();
-        permy.shuffle(&mut rng);
+        permy.shuffle(rng);
         let mut permz: Vec<_> = (0..size).collect();
-        permz.shuffle(&mut rng);
+        permz.shuffle(rng);
 
         Perlin {
             vecs,
@@ -39,19 +40,16 @@ impl Perlin {
         let j = p.y.floor() as i32;
         let k = p.z.floor() as i32;
 
-        let mut c: [[[Vec3; 2]; 2]; 2] = [[[Vec3::zero(); 2]; 2]; 2];
-
-        for di in 0..2 {
-            for dj in 0..2 {
-                for dk in 0..2 {
+        let c: [[[Vec3; 2]; 2]; 2] = std::array::from_fn(|di| {
+            std::array::from_fn(|dj| {
+                std::array::from_fn(|dk| {
                     let xi = ((i + di as i32) & 255) as usize;
                     let yi = ((j + dj as i32) & 255) as usize;
                     let zi = ((k + dk as i32) & 255) as usize;
-                    let fi = self.permx[xi] ^ self.permy[yi] ^ self.permz[zi];
-                    c[di][dj][dk] = self.vecs[fi];
-                }
-            }
-        }
+                    self.vecs[self.permx[xi] ^ self.permy[yi] ^ self.permz[zi]]
+                })
+            })
+        });
 
         Self::perlin_interpolation(&c, &parts_smoothed)
     }
@@ -60,14 +58,14 @@ impl Perlin {
         let pp = p * p * (Vec3::full(3.0) - 2.0 * p);
         let mut acc = 0.0;
 
-        for i in 0..2 {
-            for j in 0..2 {
-                for k in 0..2 {
+        for (i, plane) in c.iter().enumerate() {
+            for (j, row) in plane.iter().enumerate() {
+                for (k, gradient) in row.iter().enumerate() {
                     let ijk = Vec3::new(i as f64, j as f64, k as f64);
 
                     let weight_v = p - ijk;
                     acc += (ijk * pp + (Vec3::one() - ijk) * (Vec3::one() - pp)).product()
-                        * dot(&c[i][j][k], &weight_v);
+                        * dot(gradient, &weight_v);
                 }
             }
         }
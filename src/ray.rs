@@ -6,14 +6,26 @@ pub struct Ray {
     pub origin: Point3,
     pub direction: Vec3,
     pub time: Time,
+    /// Uniform number in (0, 1] that picks how far into a participating medium the ray
+    /// scatters. Renders draw it from the sampler for every ray they trace.
+    pub medium_sample: f64,
 }
 
 impl Ray {
+    /// A ray for geometric queries that never enter a participating medium, such as
+    /// sampling a point on a light. Its medium sample is fixed at 0.5; rays traced through a
+    /// scene are made with [`Ray::sampled`] instead.
     pub fn new(origin: Point3, direction: Vec3, time: Time) -> Ray {
+        Ray::sampled(origin, direction, time, 0.5)
+    }
+
+    /// A ray whose medium sample is drawn from the uniform number `u` in [0, 1).
+    pub fn sampled(origin: Point3, direction: Vec3, time: Time, u: f64) -> Ray {
         Ray {
             origin,
             direction,
             time,
+            medium_sample: 1.0 - u,
         }
     }
 
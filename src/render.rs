@@ -0,0 +1,622 @@
+use crate::bvh::{BvhNode, BvhSplit, LinearBvh};
+use crate::camera::Camera;
+use crate::hittable::SharedHittable;
+use crate::integrator::{Integrator, IntegratorType};
+use crate::light::LightList;
+use crate::sampler::{Sampler, SamplerType};
+use crate::scene::Scene;
+use crate::tile::{split_pixels, tiles, TileOrder};
+use crate::util::*;
+use rayon::prelude::*;
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+/// How an image is rendered, independent of what is in the scene.
+#[derive(Debug, Copy, Clone)]
+pub struct RenderSettings {
+    pub image_width: i32,
+    pub image_height: i32,
+    pub samples_per_pixel: i32,
+    /// Most segments a path may have, 0 for no limit
+    pub max_depth: i32,
+    /// Bounces before Russian roulette may end a path
+    pub roulette_depth: i32,
+    pub bvh_split: BvhSplit,
+    /// Use the pointer-based `BvhNode` tree instead of the flattened `LinearBvh`
+    pub bvh_tree: bool,
+    /// Seeds the random numbers of every sample; the same seed renders the same image
+    pub seed: u64,
+    /// Width and height in pixels of the tiles handed to the render threads
+    pub tile_size: u32,
+    pub tile_order: TileOrder,
+    /// Samples per pixel added by each progressive pass
+    pub pass_samples: i32,
+    /// Stop sampling a pixel once its estimated error is below this, 0 to disable adaptive
+    /// sampling; `samples_per_pixel` is then the most samples a pixel gets
+    pub adaptive_threshold: f64,
+    /// Samples every pixel gets before adaptive sampling may stop it
+    pub min_samples: i32,
+    pub sampler: SamplerType,
+    /// Sample the scene's lights directly at diffuse bounces, rather than only finding them
+    /// when a bounce happens to hit one
+    pub light_sampling: bool,
+    pub integrator: IntegratorType,
+    /// How far away geometry occludes with `IntegratorType::AmbientOcclusion`
+    pub ao_distance: f64,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            image_width: 400,
+            image_height: 225,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            roulette_depth: 3,
+            bvh_split: BvhSplit::Sah,
+            bvh_tree: false,
+            seed: 0,
+            tile_size: 16,
+            tile_order: TileOrder::Spiral,
+            pass_samples: 16,
+            adaptive_threshold: 0.0,
+            min_samples: 16,
+            sampler: SamplerType::Sobol,
+            light_sampling: true,
+            integrator: IntegratorType::Path,
+            ao_distance: f64::INFINITY,
+        }
+    }
+}
+
+impl RenderSettings {
+    /// The settings the scene asks for, with the height following from its aspect ratio.
+    pub fn from_scene(scene: &Scene) -> RenderSettings {
+        RenderSettings {
+            image_width: scene.image_width,
+            image_height: (scene.image_width as f64 / scene.aspect_ratio) as i32,
+            samples_per_pixel: scene.samples_per_pixel,
+            max_depth: scene.max_depth,
+            ..RenderSettings::default()
+        }
+    }
+
+    /// Checks that the settings describe an image that can be rendered.
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        // Pixels are spread from the left to the right edge, which takes at least two of them
+        if self.image_width < 2 || self.image_height < 2 {
+            return Err(SettingsError(format!(
+                "image must be at least 2x2 pixels, got {}x{}",
+                self.image_width, self.image_height
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Render settings that [`Renderer::new`] cannot render with.
+#[derive(Debug)]
+pub struct SettingsError(String);
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for SettingsError {}
+
+/// Rendered image as linear radiance, in row-major order with the top row first.
+#[derive(Debug, Clone)]
+pub struct Framebuffer {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Color>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![Color::zero(); width as usize * height as usize],
+        }
+    }
+
+    /// Returns the pixel in column `x` and row `y`, counting rows from the top.
+    pub fn pixel(&self, x: u32, y: u32) -> Color {
+        self.pixels[(y * self.width + x) as usize]
+    }
+}
+
+fn luminance(c: &Color) -> f64 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+/// Running sums of the samples taken in a pixel.
+#[derive(Debug, Copy, Clone)]
+pub struct PixelStats {
+    pub sum: Color,
+    /// Sum of the squared luminance of the samples
+    pub sum_sq: f64,
+    pub samples: u32,
+}
+
+impl Default for PixelStats {
+    fn default() -> Self {
+        PixelStats {
+            sum: Color::zero(),
+            sum_sq: 0.0,
+            samples: 0,
+        }
+    }
+}
+
+impl PixelStats {
+    pub fn add(&mut self, sample: Color) {
+        self.sum += sample;
+        self.sum_sq += luminance(&sample).powi(2);
+        self.samples += 1;
+    }
+
+    pub fn mean(&self) -> Color {
+        if self.samples > 0 {
+            self.sum / self.samples as f64
+        } else {
+            self.sum
+        }
+    }
+
+    /// Estimated standard error of the pixel after gamma correction, where the noise is seen.
+    pub fn error(&self) -> f64 {
+        if self.samples < 2 {
+            return f64::INFINITY;
+        }
+        let n = self.samples as f64;
+        let mean = luminance(&self.sum) / n;
+        let variance = ((self.sum_sq / n - mean * mean) * n / (n - 1.0)).max(0.0);
+        // The gamma-corrected value is sqrt(mean), which changes by d(mean) / (2 sqrt(mean))
+        (variance / n).sqrt() / (2.0 * mean.max(0.0).sqrt()).max(1.0e-3)
+    }
+}
+
+/// Running sums of the samples taken in every pixel, from which progressive passes continue.
+#[derive(Debug, Clone)]
+pub struct Accumulator {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<PixelStats>,
+}
+
+impl Accumulator {
+    pub fn new(width: u32, height: u32) -> Accumulator {
+        Accumulator {
+            width,
+            height,
+            pixels: vec![PixelStats::default(); width as usize * height as usize],
+        }
+    }
+
+    /// The fewest samples taken in any pixel.
+    pub fn min_samples(&self) -> u32 {
+        self.pixels.iter().map(|p| p.samples).min().unwrap_or(0)
+    }
+
+    /// The average number of samples taken per pixel.
+    pub fn mean_samples(&self) -> f64 {
+        let total: u64 = self.pixels.iter().map(|p| p.samples as u64).sum();
+        total as f64 / self.pixels.len().max(1) as f64
+    }
+
+    /// The average of the samples in every pixel.
+    pub fn image(&self) -> Framebuffer {
+        Framebuffer {
+            width: self.width,
+            height: self.height,
+            pixels: self.pixels.iter().map(PixelStats::mean).collect(),
+        }
+    }
+
+    /// A grayscale image of the samples taken per pixel, white at `max_samples`.
+    pub fn samples_image(&self, max_samples: u32) -> Framebuffer {
+        let scale = 1.0 / max_samples.max(1) as f64;
+        Framebuffer {
+            width: self.width,
+            height: self.height,
+            pixels: self
+                .pixels
+                .iter()
+                .map(|p| Color::full(p.samples as f64 * scale))
+                .collect(),
+        }
+    }
+}
+
+pub struct Renderer {
+    world: SharedHittable,
+    lights: LightList,
+    integrator: Box<dyn Integrator + Send + Sync>,
+    camera: Camera,
+    background: Color,
+    settings: RenderSettings,
+}
+
+impl Renderer {
+    /// Builds the BVH over the scene's objects, collects its lights and sets up its camera for
+    /// the image size in `settings`, which must pass [`RenderSettings::validate`].
+    pub fn new(scene: Scene, settings: RenderSettings) -> Result<Renderer, SettingsError> {
+        settings.validate()?;
+
+        let Scene {
+            objects,
+            background,
+            camera,
+            ..
+        } = scene;
+
+        let (time0, time1) = (camera.time0, camera.time1);
+        let aspect_ratio = settings.image_width as f64 / settings.image_height as f64;
+        let camera = camera.camera(aspect_ratio);
+
+        let (objects, lights) = if settings.light_sampling {
+            LightList::collect(objects)
+        } else {
+            (objects, LightList::default())
+        };
+        let world = if settings.bvh_tree {
+            BvhNode::from_objects(time0, time1, objects, settings.bvh_split)
+        } else {
+            LinearBvh::from_objects(time0, time1, objects, settings.bvh_split)
+        };
+
+        Ok(Renderer {
+            world,
+            lights,
+            integrator: settings.integrator.create(&settings),
+            camera,
+            background,
+            settings,
+        })
+    }
+
+    pub fn settings(&self) -> &RenderSettings {
+        &self.settings
+    }
+
+    pub fn render(&self) -> Framebuffer {
+        let mut accumulator = self.accumulator();
+        self.render_progressive(&mut accumulator, |_| ());
+        accumulator.image()
+    }
+
+    /// Returns an empty accumulator of the size of the image.
+    pub fn accumulator(&self) -> Accumulator {
+        let RenderSettings {
+            image_width,
+            image_height,
+            ..
+        } = self.settings;
+        Accumulator::new(image_width as u32, image_height as u32)
+    }
+
+    /// Adds samples to `accumulator` in passes of `pass_samples` samples per pixel, calling
+    /// `on_pass` after each pass. Pixels stop being sampled once they have
+    /// `samples_per_pixel` samples or, with adaptive sampling, once their estimated error is
+    /// below `adaptive_threshold`.
+    ///
+    /// Samples are numbered per pixel and added to the sums in order, so continuing from an
+    /// accumulator gives the same result as rendering all samples at once.
+    pub fn render_progressive(
+        &self,
+        accumulator: &mut Accumulator,
+        mut on_pass: impl FnMut(&Accumulator),
+    ) {
+        while self.render_pass(accumulator) > 0 {
+            on_pass(accumulator);
+        }
+    }
+
+    fn converged(&self, pixel: &PixelStats) -> bool {
+        let RenderSettings {
+            samples_per_pixel,
+            min_samples,
+            adaptive_threshold,
+            ..
+        } = self.settings;
+
+        pixel.samples >= samples_per_pixel.max(0) as u32
+            || (adaptive_threshold > 0.0
+                && pixel.samples >= min_samples.max(0) as u32
+                && pixel.error() < adaptive_threshold)
+    }
+
+    /// Adds up to `pass_samples` samples to every pixel that has not converged, and returns
+    /// how many pixels were sampled.
+    fn render_pass(&self, accumulator: &mut Accumulator) -> usize {
+        let RenderSettings {
+            samples_per_pixel,
+            pass_samples,
+            tile_size,
+            tile_order,
+            sampler,
+            seed,
+            ..
+        } = self.settings;
+        let max_samples = samples_per_pixel.max(0) as u32;
+        let pass_samples = pass_samples.max(1) as u32;
+        let (width, height) = (accumulator.width, accumulator.height);
+
+        let tiles = tiles(width, height, tile_size, tile_order);
+        let pixels = split_pixels(&mut accumulator.pixels, width, tile_size, &tiles);
+
+        // par_bridge hands out tiles in order as threads become free
+        tiles
+            .into_iter()
+            .zip(pixels)
+            .par_bridge()
+            .map(|(tile, rows)| {
+                let mut sampler = sampler.create(seed, max_samples);
+                let mut sampled = 0;
+                for (dy, row) in rows.into_iter().enumerate() {
+                    for (dx, pixel) in row.iter_mut().enumerate() {
+                        if self.converged(pixel) {
+                            continue;
+                        }
+                        let (x, y) = (tile.x + dx as u32, tile.y + dy as u32);
+                        let target = (pixel.samples + pass_samples).min(max_samples);
+                        self.add_samples(x, y, pixel.samples..target, sampler.as_mut(), pixel);
+                        sampled += 1;
+                    }
+                }
+                sampled
+            })
+            .sum()
+    }
+
+    /// Adds samples `samples` of the pixel in column `x` and row `y` to `stats`, counting rows
+    /// from the top.
+    fn add_samples(
+        &self,
+        x: u32,
+        y: u32,
+        samples: Range<u32>,
+        sampler: &mut dyn Sampler,
+        stats: &mut PixelStats,
+    ) {
+        let RenderSettings {
+            image_width,
+            image_height,
+            ..
+        } = self.settings;
+
+        let pixel = y as u64 * image_width as u64 + x as u64;
+        let i = x as f64;
+        let j = (image_height as u32 - 1 - y) as f64;
+
+        for s in samples {
+            sampler.start_pixel_sample(pixel, s);
+            let (dx, dy) = sampler.get_2d();
+            let u = (i + dx) / (image_width - 1) as f64;
+            let v = (j + dy) / (image_height - 1) as f64;
+
+            let r = self.camera.get_ray(u, v, sampler);
+            stats.add(self.integrator.ray_color(
+                &r,
+                self.world.as_ref(),
+                &self.lights,
+                &self.background,
+                sampler,
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aarect::Rect2D;
+    use crate::material::{Dielectric, DiffuseLight, Lambertian, SharedMaterial};
+    use crate::scene::CameraSettings;
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColor;
+    use crate::vec3::Vec3;
+
+    fn light_scene() -> Scene {
+        let light = DiffuseLight::new(SolidColor::new(Color::new(1.0, 0.5, 0.25)));
+        let camera = CameraSettings {
+            look_from: Point3::new(0.0, 0.0, 5.0),
+            look_at: Point3::zero(),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            vfov: 20.0,
+            aperture: 0.0,
+            focus_dist: 5.0,
+            time0: 0.0,
+            time1: 1.0,
+        };
+        Scene::new(
+            vec![Sphere::new(Point3::zero(), 0.5, light)],
+            Color::zero(),
+            camera,
+        )
+    }
+
+    #[test]
+    fn test_render() {
+        let scene = light_scene();
+        let settings = RenderSettings {
+            image_width: 16,
+            image_height: 8,
+            samples_per_pixel: 4,
+            ..RenderSettings::from_scene(&scene)
+        };
+
+        let image = Renderer::new(scene, settings).unwrap().render();
+
+        assert_eq!(
+            (image.width, image.height, image.pixels.len()),
+            (16, 8, 128)
+        );
+        assert_eq!(image.pixel(8, 4).as_tuple(), (1.0, 0.5, 0.25));
+        assert_eq!(image.pixel(0, 0).as_tuple(), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_image_too_small() {
+        for (width, height) in [(1, 8), (16, 1), (0, 0)] {
+            let scene = light_scene();
+            let settings = RenderSettings {
+                image_width: width,
+                image_height: height,
+                ..RenderSettings::from_scene(&scene)
+            };
+            assert!(Renderer::new(scene, settings).is_err());
+        }
+    }
+
+    #[test]
+    fn test_adaptive_sampling() {
+        let scene = light_scene();
+        let settings = RenderSettings {
+            image_width: 16,
+            image_height: 8,
+            samples_per_pixel: 64,
+            pass_samples: 4,
+            min_samples: 4,
+            adaptive_threshold: 0.01,
+            ..RenderSettings::from_scene(&scene)
+        };
+        let renderer = Renderer::new(scene, settings).unwrap();
+        let mut accumulator = renderer.accumulator();
+        renderer.render_progressive(&mut accumulator, |_| ());
+
+        // Flat pixels stop after the minimum, pixels on the edge of the sphere keep going
+        let samples: Vec<u32> = accumulator.pixels.iter().map(|p| p.samples).collect();
+        assert_eq!(samples[0], 4);
+        assert_eq!(samples[4 * 16 + 8], 4);
+        assert!(samples.iter().any(|&n| n > 4));
+        assert!(samples.iter().all(|&n| n <= 64));
+    }
+
+    /// Renders a floor lit by a sphere and a rectangle light, returning the average of the
+    /// bottom rows, which only see the floor. With `medium`, the lights and the floor around
+    /// them sit inside a sphere of it that the camera is outside.
+    fn lit_floor_mean(
+        medium: Option<SharedMaterial>,
+        configure: impl FnOnce(&mut RenderSettings),
+    ) -> f64 {
+        let light = DiffuseLight::new(SolidColor::new(Color::new(4.0, 4.0, 4.0)));
+        let white = Lambertian::new(SolidColor::new(Color::full(0.5)));
+        let mut scene = light_scene();
+        scene.objects = vec![
+            Sphere::new(Point3::new(0.0, -100.5, 0.0), 100.0, white),
+            Sphere::new(Point3::new(-0.5, 0.5, 0.0), 0.25, light.clone()),
+            Rect2D::new_xz(0.0, 1.0, -1.0, 0.0, 1.0, light),
+        ];
+        if let Some(medium) = medium {
+            scene.objects.push(Sphere::new(Point3::zero(), 3.0, medium));
+        }
+        let mut settings = RenderSettings {
+            image_width: 16,
+            image_height: 8,
+            samples_per_pixel: 4096,
+            ..RenderSettings::from_scene(&scene)
+        };
+        configure(&mut settings);
+
+        let image = Renderer::new(scene, settings).unwrap().render();
+        image.pixels[16 * 5..].iter().map(|c| c.sum()).sum::<f64>() / (16.0 * 3.0)
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 0.05 * b, "{} vs {}", a, b);
+    }
+
+    #[test]
+    fn test_light_sampling_is_unbiased() {
+        let bounced = lit_floor_mean(None, |s| s.light_sampling = false);
+        assert_close(lit_floor_mean(None, |_| ()), bounced);
+    }
+
+    #[test]
+    fn test_russian_roulette_is_unbiased() {
+        let full = lit_floor_mean(None, |s| s.roulette_depth = i32::MAX);
+        assert_close(lit_floor_mean(None, |s| s.roulette_depth = 0), full);
+    }
+
+    #[test]
+    fn test_light_sampling_through_medium_is_unbiased() {
+        // Index-matched glass, so that shadow rays and bounces alike only lose light to absorption
+        let absorption = Dielectric::absorption_for(Color::new(0.9, 0.7, 0.5), 1.0);
+        let glass = Dielectric::tinted(1.0, 0.0, 0.0, absorption);
+        let bounced = lit_floor_mean(Some(glass.clone()), |s| s.light_sampling = false);
+        let sampled = lit_floor_mean(Some(glass), |_| ());
+        assert_close(sampled, bounced);
+        assert!(sampled < 0.9 * lit_floor_mean(None, |_| ()));
+    }
+
+    fn render_with_threads(scene: i32, seed: u64, threads: usize) -> Vec<(u64, u64, u64)> {
+        let scene = crate::scenes::builtin_scene(scene, seed).unwrap();
+        let settings = RenderSettings {
+            image_width: 12,
+            image_height: 12,
+            samples_per_pixel: 2,
+            seed,
+            ..RenderSettings::from_scene(&scene)
+        };
+        let renderer = Renderer::new(scene, settings).unwrap();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        let image = pool.install(|| renderer.render());
+        image
+            .pixels
+            .iter()
+            .map(|c| (c.x.to_bits(), c.y.to_bits(), c.z.to_bits()))
+            .collect()
+    }
+
+    #[test]
+    fn test_render_is_reproducible() {
+        // Random spheres with defocus blur and motion blur, and the Cornell box with smoke
+        for scene in [1, 9] {
+            let image = render_with_threads(scene, 7, 1);
+            assert_eq!(image, render_with_threads(scene, 7, 3));
+            assert_ne!(image, render_with_threads(scene, 8, 1));
+        }
+    }
+
+    #[test]
+    fn test_progressive_resume() {
+        let scene = crate::scenes::builtin_scene(1, 0).unwrap();
+        let settings = RenderSettings {
+            image_width: 8,
+            image_height: 6,
+            samples_per_pixel: 5,
+            pass_samples: 5,
+            ..RenderSettings::from_scene(&scene)
+        };
+        let renderer = Renderer::new(scene, settings).unwrap();
+        let image = renderer.render();
+
+        // Two samples per pass, stopping after the first pass and continuing afterwards
+        let progressive = Renderer {
+            settings: RenderSettings {
+                pass_samples: 2,
+                ..settings
+            },
+            ..renderer
+        };
+        let mut accumulator = progressive.accumulator();
+        let mut passes = vec![];
+        progressive.render_pass(&mut accumulator);
+        progressive.render_progressive(&mut accumulator, |acc| passes.push(acc.min_samples()));
+
+        assert_eq!(passes, [4, 5]);
+        let resumed = accumulator.image();
+        for (a, b) in image.pixels.iter().zip(&resumed.pixels) {
+            assert_eq!(a.as_tuple(), b.as_tuple());
+        }
+    }
+}
@@ -0,0 +1,361 @@
+use crate::util::{hash_u64, rand, sample_rng, Rng};
+
+/// Supplies the random numbers of a camera sample, one dimension at a time.
+///
+/// The pixel position takes the first two dimensions, then the lens, the time and whatever
+/// the materials along the path ask for. Samplers other than `Independent` spread the samples
+/// of a pixel evenly over every dimension, which converges faster than independent random
+/// numbers. The numbers depend only on the seed, pixel, sample index and dimension, so renders
+/// are reproducible.
+pub trait Sampler {
+    /// Starts sample `index` of pixel `pixel`, at the first dimension.
+    fn start_pixel_sample(&mut self, pixel: u64, index: u32);
+    fn get_1d(&mut self) -> f64;
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SamplerType {
+    /// Independent uniform random numbers
+    Independent,
+    /// Jittered strata, shuffled independently per dimension
+    Stratified,
+    /// The Halton sequence, randomized per pixel with Cranley-Patterson rotation
+    Halton,
+    /// 2D Sobol points with hash-based Owen scrambling, shuffled independently per dimension
+    Sobol,
+}
+
+impl SamplerType {
+    /// Creates a sampler for images with up to `samples_per_pixel` samples in each pixel.
+    pub fn create(self, seed: u64, samples_per_pixel: u32) -> Box<dyn Sampler + Send> {
+        match self {
+            SamplerType::Independent => Box::new(IndependentSampler {
+                seed,
+                rng: sample_rng(seed, 0, 0),
+            }),
+            SamplerType::Stratified => Box::new(StratifiedSampler {
+                state: SampleState::new(seed),
+                samples_per_pixel: samples_per_pixel.max(1),
+            }),
+            SamplerType::Halton => Box::new(HaltonSampler {
+                state: SampleState::new(seed),
+            }),
+            SamplerType::Sobol => Box::new(SobolSampler {
+                state: SampleState::new(seed),
+            }),
+        }
+    }
+}
+
+/// Converts the high bits of `h` to a uniform number in [0, 1).
+fn to_unit(h: u64) -> f64 {
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn u32_to_unit(x: u32) -> f64 {
+    x as f64 / (1u64 << 32) as f64
+}
+
+/// The pixel, sample and next dimension of the current sample.
+struct SampleState {
+    seed: u64,
+    pixel: u64,
+    index: u32,
+    dimension: u64,
+}
+
+impl SampleState {
+    fn new(seed: u64) -> SampleState {
+        SampleState {
+            seed,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn start(&mut self, pixel: u64, index: u32) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    /// Takes the next `n` dimensions, returning the first and a hash that is the same for all
+    /// samples of the pixel in that dimension.
+    fn next_dimensions(&mut self, n: u64) -> (u64, u64) {
+        let dimension = self.dimension;
+        self.dimension += n;
+        let hash = hash_u64(hash_u64(hash_u64(self.seed) ^ self.pixel) ^ dimension);
+        (dimension, hash)
+    }
+}
+
+pub struct IndependentSampler {
+    seed: u64,
+    rng: Rng,
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, pixel: u64, index: u32) {
+        self.rng = sample_rng(self.seed, pixel, index as u64);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        rand(&mut self.rng)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (rand(&mut self.rng), rand(&mut self.rng))
+    }
+}
+
+/// Returns element `i` of a random permutation of `0..n` chosen by `seed` (Kensler,
+/// "Correlated Multi-Jittered Sampling").
+fn permute(mut i: u32, n: u32, seed: u32) -> u32 {
+    let mut w = n.wrapping_sub(1);
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    // Cycle-walk until the permuted index is in range
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < n {
+            return i.wrapping_add(seed) % n;
+        }
+    }
+}
+
+pub struct StratifiedSampler {
+    state: SampleState,
+    samples_per_pixel: u32,
+}
+
+impl StratifiedSampler {
+    /// The stratum of the current sample among `n`, shuffled per dimension.
+    fn stratum(&self, n: u32, hash: u64) -> u32 {
+        permute(self.state.index % n, n, hash as u32)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, pixel: u64, index: u32) {
+        self.state.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (_, hash) = self.state.next_dimensions(1);
+        let n = self.samples_per_pixel;
+        let jitter = to_unit(hash_u64(hash ^ self.state.index as u64));
+        (self.stratum(n, hash) as f64 + jitter) / n as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (_, hash) = self.state.next_dimensions(2);
+        let nx = (self.samples_per_pixel as f64).sqrt().ceil() as u32;
+        let ny = self.samples_per_pixel.div_ceil(nx);
+        let stratum = self.stratum(nx * ny, hash);
+        let jitter = hash_u64(hash ^ self.state.index as u64);
+        let (jx, jy) = (to_unit(jitter), to_unit(hash_u64(jitter)));
+        (
+            ((stratum % nx) as f64 + jx) / nx as f64,
+            ((stratum / nx) as f64 + jy) / ny as f64,
+        )
+    }
+}
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+fn radical_inverse(base: u32, mut i: u32) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv = 1.0;
+    let mut result = 0.0;
+    while i > 0 {
+        inv *= inv_base;
+        result += (i % base) as f64 * inv;
+        i /= base;
+    }
+    result
+}
+
+pub struct HaltonSampler {
+    state: SampleState,
+}
+
+impl HaltonSampler {
+    fn halton(&self, dimension: u64, hash: u64) -> f64 {
+        let offset = to_unit(hash_u64(hash ^ dimension));
+        match PRIMES.get(dimension as usize) {
+            Some(&base) => (radical_inverse(base, self.state.index) + offset).fract(),
+            // Past the last base, fall back to random numbers
+            None => to_unit(hash_u64(hash ^ self.state.index as u64)),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, pixel: u64, index: u32) {
+        self.state.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (dimension, hash) = self.state.next_dimensions(1);
+        self.halton(dimension, hash)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (dimension, hash) = self.state.next_dimensions(2);
+        (
+            self.halton(dimension, hash),
+            self.halton(dimension + 1, hash),
+        )
+    }
+}
+
+/// Random permutation of 32-bit integers in which each bit depends only on the bits below it
+/// (Laine and Karras; constants from Burley, "Practical Hash-based Owen Scrambling").
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+/// Owen scrambling of the bits of `x`, read as a binary fraction.
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+/// The second dimension of the Sobol sequence; the first is `i.reverse_bits()`.
+fn sobol_second(mut i: u32) -> u32 {
+    let mut v = 1u32 << 31;
+    let mut result = 0;
+    while i != 0 {
+        if i & 1 != 0 {
+            result ^= v;
+        }
+        i >>= 1;
+        v ^= v >> 1;
+    }
+    result
+}
+
+pub struct SobolSampler {
+    state: SampleState,
+}
+
+impl SobolSampler {
+    /// The sample index shuffled per dimension, which decorrelates the dimensions while
+    /// keeping the points of every dimension well distributed.
+    fn shuffled_index(&self, hash: u64) -> u32 {
+        nested_uniform_scramble(self.state.index, hash as u32)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, pixel: u64, index: u32) {
+        self.state.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (_, hash) = self.state.next_dimensions(1);
+        let i = self.shuffled_index(hash);
+        u32_to_unit(nested_uniform_scramble(
+            i.reverse_bits(),
+            (hash >> 32) as u32,
+        ))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (_, hash) = self.state.next_dimensions(2);
+        let i = self.shuffled_index(hash);
+        let seeds = hash_u64(hash);
+        (
+            u32_to_unit(nested_uniform_scramble(i.reverse_bits(), seeds as u32)),
+            u32_to_unit(nested_uniform_scramble(
+                sobol_second(i),
+                (seeds >> 32) as u32,
+            )),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TYPES: [SamplerType; 4] = [
+        SamplerType::Independent,
+        SamplerType::Stratified,
+        SamplerType::Halton,
+        SamplerType::Sobol,
+    ];
+
+    #[test]
+    fn test_samples_in_unit_interval() {
+        for sampler_type in TYPES {
+            let mut sampler = sampler_type.create(3, 16);
+            for index in 0..16 {
+                sampler.start_pixel_sample(42, index);
+                for _ in 0..40 {
+                    let x = sampler.get_1d();
+                    let (y, z) = sampler.get_2d();
+                    assert!([x, y, z].iter().all(|v| (0.0..1.0).contains(v)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_stratification() {
+        // One of 16 samples in each 1/4 x 1/4 cell, in every pair of dimensions
+        for sampler_type in [SamplerType::Stratified, SamplerType::Sobol] {
+            let mut sampler = sampler_type.create(5, 16);
+            for dimension in 0..3 {
+                let mut cells = [0; 16];
+                for index in 0..16 {
+                    sampler.start_pixel_sample(7, index);
+                    for _ in 0..dimension {
+                        sampler.get_2d();
+                    }
+                    let (x, y) = sampler.get_2d();
+                    cells[(y * 4.0) as usize * 4 + (x * 4.0) as usize] += 1;
+                }
+                assert_eq!(cells, [1; 16], "{:?} dimension {}", sampler_type, dimension);
+            }
+        }
+    }
+
+    #[test]
+    fn test_permute() {
+        for n in [1, 5, 16, 100] {
+            let mut seen: Vec<u32> = (0..n).map(|i| permute(i, n, 0xdeadbeef)).collect();
+            seen.sort();
+            assert_eq!(seen, (0..n).collect::<Vec<_>>());
+        }
+    }
+}
@@ -0,0 +1,1134 @@
+use crate::aarect::Rect2D;
+use crate::camera::Camera;
+use crate::constant_medium::ConstantMedium;
+use crate::cube::Cube;
+use crate::hittable::SharedHittable;
+use crate::material::*;
+use crate::matrix::Mat4;
+use crate::moving_sphere::MovingSphere;
+use crate::obj::load_obj;
+use crate::spectrum::*;
+use crate::sphere::Sphere;
+use crate::texture::*;
+use crate::transform::Transform;
+use crate::triangle::Triangle;
+use crate::util::*;
+use crate::vec3::Vec3;
+use rand::SeedableRng;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug, Copy, Clone)]
+pub struct CameraSettings {
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub vup: Vec3,
+    pub vfov: f64,
+    pub aperture: f64,
+    pub focus_dist: f64,
+    pub time0: Time,
+    pub time1: Time,
+}
+
+impl CameraSettings {
+    pub fn camera(&self, aspect_ratio: f64) -> Camera {
+        Camera::new(
+            &self.look_from,
+            &self.look_at,
+            &self.vup,
+            self.vfov,
+            aspect_ratio,
+            self.aperture,
+            self.focus_dist,
+            self.time0,
+            self.time1,
+        )
+    }
+}
+
+/// Everything needed to render an image: the objects, how they are viewed and the render
+/// settings.
+pub struct Scene {
+    pub objects: Vec<SharedHittable>,
+    pub background: Color,
+    pub camera: CameraSettings,
+    pub aspect_ratio: f64,
+    pub image_width: i32,
+    pub samples_per_pixel: i32,
+    pub max_depth: i32,
+}
+
+impl Scene {
+    pub fn new(objects: Vec<SharedHittable>, background: Color, camera: CameraSettings) -> Scene {
+        Scene {
+            objects,
+            background,
+            camera,
+            aspect_ratio: 16.0 / 9.0,
+            image_width: 400,
+            samples_per_pixel: 100,
+            max_depth: 50,
+        }
+    }
+
+    /// Loads a scene from a TOML scene description file.
+    ///
+    /// Relative paths to meshes and images are resolved against the scene file's directory.
+    pub fn load(path: impl AsRef<Path>) -> Result<Scene, SceneError> {
+        let path = path.as_ref();
+        let src = fs::read_to_string(path).map_err(|source| SceneError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::parse(&src, path)
+    }
+
+    fn parse(src: &str, path: &Path) -> Result<Scene, SceneError> {
+        let spec: SceneSpec = toml::from_str(src).map_err(|source| SceneError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        let mut builder = SceneBuilder {
+            path,
+            dir: path.parent().unwrap_or_else(|| Path::new("")),
+            texture_specs: &spec.textures,
+            textures: HashMap::new(),
+            resolving: HashSet::new(),
+            materials: HashMap::new(),
+        };
+
+        let render = &spec.render;
+        builder.positive("aspect_ratio", render.aspect_ratio, "render")?;
+        builder.positive("image_width", render.image_width, "render")?;
+        builder.positive("samples_per_pixel", render.samples_per_pixel, "render")?;
+        if render.max_depth < 0 {
+            return Err(builder.error("render", "max_depth must be at least 0 (no limit)"));
+        }
+
+        let camera = &spec.camera;
+        if !(camera.vfov > 0.0 && camera.vfov < 180.0) {
+            return Err(builder.error(
+                "camera",
+                format!(
+                    "vfov must be between 0 and 180 degrees, got {}",
+                    camera.vfov
+                ),
+            ));
+        }
+        if !(camera.aperture >= 0.0 && camera.aperture.is_finite()) {
+            return Err(builder.error(
+                "camera",
+                format!("aperture must be at least 0, got {}", camera.aperture),
+            ));
+        }
+
+        for (name, material) in &spec.materials {
+            let entry = format!("materials.{}", name);
+            let material = builder.material(material, &entry)?;
+            builder.materials.insert(name.clone(), material);
+        }
+
+        let objects = spec
+            .objects
+            .iter()
+            .enumerate()
+            .map(|(i, object)| builder.object(object, &format!("objects[{}]", i)))
+            .collect::<Result<_, _>>()?;
+
+        let look_from = vec3(camera.look_from);
+        let look_at = vec3(camera.look_at);
+        let camera = CameraSettings {
+            look_from,
+            look_at,
+            vup: vec3(camera.vup),
+            vfov: camera.vfov,
+            aperture: camera.aperture,
+            focus_dist: camera
+                .focus_distance
+                .unwrap_or_else(|| (look_from - look_at).mag()),
+            time0: camera.time0,
+            time1: camera.time1,
+        };
+
+        Ok(Scene {
+            objects,
+            background: vec3(spec.background),
+            camera,
+            aspect_ratio: render.aspect_ratio,
+            image_width: render.image_width,
+            samples_per_pixel: render.samples_per_pixel,
+            max_depth: render.max_depth,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    Invalid {
+        path: PathBuf,
+        entry: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            SceneError::Parse { path, source } => write!(f, "{}: {}", path.display(), source),
+            SceneError::Invalid {
+                path,
+                entry,
+                message,
+            } => write!(f, "{}: {}: {}", path.display(), entry, message),
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SceneError::Io { source, .. } => Some(source),
+            SceneError::Parse { source, .. } => Some(source),
+            SceneError::Invalid { .. } => None,
+        }
+    }
+}
+
+fn vec3(v: [f64; 3]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+fn default_vup() -> [f64; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn default_time1() -> Time {
+    1.0
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneSpec {
+    camera: CameraSpec,
+    #[serde(default)]
+    background: [f64; 3],
+    #[serde(default)]
+    render: RenderSpec,
+    #[serde(default)]
+    textures: BTreeMap<String, TextureSpec>,
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialSpec>,
+    #[serde(default)]
+    objects: Vec<ObjectSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraSpec {
+    look_from: [f64; 3],
+    look_at: [f64; 3],
+    #[serde(default = "default_vup")]
+    vup: [f64; 3],
+    vfov: f64,
+    #[serde(default)]
+    aperture: f64,
+    focus_distance: Option<f64>,
+    #[serde(default)]
+    time0: Time,
+    #[serde(default = "default_time1")]
+    time1: Time,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RenderSpec {
+    aspect_ratio: f64,
+    image_width: i32,
+    samples_per_pixel: i32,
+    max_depth: i32,
+}
+
+impl Default for RenderSpec {
+    fn default() -> RenderSpec {
+        RenderSpec {
+            aspect_ratio: 16.0 / 9.0,
+            image_width: 400,
+            samples_per_pixel: 100,
+            max_depth: 50,
+        }
+    }
+}
+
+/// An inline color, a gray level or the name of an entry in `[textures]`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TextureRef {
+    Color([f64; 3]),
+    Gray(f64),
+    Name(String),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureSpec {
+    Solid {
+        color: [f64; 3],
+    },
+    Checker {
+        odd: TextureRef,
+        even: TextureRef,
+    },
+    Noise {
+        scale: f64,
+        /// Seeds the noise pattern
+        #[serde(default)]
+        seed: u64,
+    },
+    Image {
+        path: PathBuf,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialSpec {
+    Lambertian {
+        albedo: TextureRef,
+    },
+    Metal {
+        albedo: [f64; 3],
+        #[serde(default)]
+        fuzz: f64,
+    },
+    /// A GGX metal, with either `f0` (the color at normal incidence) or `eta` and `k`
+    Conductor {
+        f0: Option<[f64; 3]>,
+        eta: Option<[f64; 3]>,
+        k: Option<[f64; 3]>,
+        #[serde(default)]
+        roughness: RoughnessSpec,
+    },
+    /// Glass, tinted by either an `absorption` coefficient, or the `transmittance` through a
+    /// `distance` that defaults to 1
+    Dielectric {
+        ior: IorSpec,
+        #[serde(default)]
+        roughness: RoughnessSpec,
+        absorption: Option<[f64; 3]>,
+        transmittance: Option<[f64; 3]>,
+        distance: Option<f64>,
+    },
+    DiffuseLight {
+        emit: TextureRef,
+    },
+    Isotropic {
+        albedo: TextureRef,
+    },
+    /// A principled BSDF; parameters left out take the defaults of `Principled`
+    Principled {
+        base_color: Option<TextureRef>,
+        metallic: Option<TextureRef>,
+        roughness: Option<TextureRef>,
+        specular: Option<TextureRef>,
+        clearcoat: Option<TextureRef>,
+        sheen: Option<TextureRef>,
+        transmission: Option<TextureRef>,
+        ior: Option<f64>,
+    },
+}
+
+/// A constant index of refraction, or the coefficients of Cauchy's or the Sellmeier equation
+/// for wavelengths in micrometres.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum IorSpec {
+    Constant(f64),
+    Cauchy {
+        cauchy: [f64; 2],
+    },
+    Sellmeier {
+        sellmeier_b: [f64; 3],
+        sellmeier_c: [f64; 3],
+    },
+}
+
+impl IorSpec {
+    fn ior(&self) -> Ior {
+        match *self {
+            IorSpec::Constant(n) => Ior::Constant(n),
+            IorSpec::Cauchy { cauchy: [a, b] } => Ior::Cauchy { a, b },
+            IorSpec::Sellmeier {
+                sellmeier_b,
+                sellmeier_c,
+            } => Ior::Sellmeier {
+                b: sellmeier_b,
+                c: sellmeier_c,
+            },
+        }
+    }
+}
+
+/// Roughness from 0 to 1, or separate roughness along the two tangents of the surface.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RoughnessSpec {
+    Isotropic(f64),
+    Anisotropic([f64; 2]),
+}
+
+impl Default for RoughnessSpec {
+    fn default() -> RoughnessSpec {
+        RoughnessSpec::Isotropic(0.0)
+    }
+}
+
+impl RoughnessSpec {
+    fn pair(&self) -> (f64, f64) {
+        match *self {
+            RoughnessSpec::Isotropic(r) => (r, r),
+            RoughnessSpec::Anisotropic([x, y]) => (x, y),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScaleSpec {
+    Uniform(f64),
+    PerAxis([f64; 3]),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RotationSpec {
+    axis: [f64; 3],
+    degrees: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectSpec {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+        material: String,
+    },
+    MovingSphere {
+        center0: [f64; 3],
+        center1: [f64; 3],
+        #[serde(default)]
+        time0: Time,
+        #[serde(default = "default_time1")]
+        time1: Time,
+        radius: f64,
+        material: String,
+    },
+    RectXy {
+        x0: f64,
+        x1: f64,
+        y0: f64,
+        y1: f64,
+        k: f64,
+        material: String,
+    },
+    RectXz {
+        x0: f64,
+        x1: f64,
+        z0: f64,
+        z1: f64,
+        k: f64,
+        material: String,
+    },
+    RectYz {
+        y0: f64,
+        y1: f64,
+        z0: f64,
+        z1: f64,
+        k: f64,
+        material: String,
+    },
+    Cube {
+        min: [f64; 3],
+        max: [f64; 3],
+        material: String,
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3],
+        normals: Option<[[f64; 3]; 3]>,
+        uvs: Option<[[f64; 2]; 3]>,
+        material: String,
+    },
+    /// A Wavefront OBJ file; `material` is used for faces without an MTL material.
+    Mesh {
+        path: PathBuf,
+        material: Option<String>,
+    },
+    ConstantMedium {
+        boundary: Box<ObjectSpec>,
+        density: f64,
+        albedo: TextureRef,
+    },
+    /// Scales, then rotates, then translates the wrapped object.
+    Transform {
+        object: Box<ObjectSpec>,
+        scale: Option<ScaleSpec>,
+        rotate: Option<RotationSpec>,
+        translate: Option<[f64; 3]>,
+    },
+}
+
+/// Turns the deserialized specs into scene objects, resolving named textures and materials.
+struct SceneBuilder<'a> {
+    path: &'a Path,
+    dir: &'a Path,
+    texture_specs: &'a BTreeMap<String, TextureSpec>,
+    textures: HashMap<String, SharedTexture>,
+    // Textures currently being built, to detect reference cycles
+    resolving: HashSet<String>,
+    materials: HashMap<String, SharedMaterial>,
+}
+
+impl<'a> SceneBuilder<'a> {
+    fn error(&self, entry: &str, message: impl Into<String>) -> SceneError {
+        SceneError::Invalid {
+            path: self.path.to_path_buf(),
+            entry: entry.to_string(),
+            message: message.into(),
+        }
+    }
+
+    /// Checks that `value`, the field `name` of `entry`, is positive and finite.
+    fn positive<T: Into<f64> + Copy>(
+        &self,
+        name: &str,
+        value: T,
+        entry: &str,
+    ) -> Result<T, SceneError> {
+        let v: f64 = value.into();
+        if v > 0.0 && v.is_finite() {
+            Ok(value)
+        } else {
+            Err(self.error(entry, format!("{} must be positive, got {}", name, v)))
+        }
+    }
+
+    /// Checks that the fields `names` of `entry` hold an increasing pair of `bounds`.
+    fn ordered(&self, names: [&str; 2], bounds: (f64, f64), entry: &str) -> Result<(), SceneError> {
+        if bounds.0 < bounds.1 {
+            Ok(())
+        } else {
+            Err(self.error(
+                entry,
+                format!(
+                    "{} must be less than {}, got {} and {}",
+                    names[0], names[1], bounds.0, bounds.1
+                ),
+            ))
+        }
+    }
+
+    fn texture(&mut self, texture: &TextureRef, entry: &str) -> Result<SharedTexture, SceneError> {
+        let name = match texture {
+            TextureRef::Color(c) => return Ok(SolidColor::new(vec3(*c))),
+            TextureRef::Gray(g) => return Ok(SolidColor::new(Color::full(*g))),
+            TextureRef::Name(name) => name,
+        };
+
+        if let Some(texture) = self.textures.get(name) {
+            return Ok(texture.clone());
+        }
+
+        let spec = self
+            .texture_specs
+            .get(name)
+            .ok_or_else(|| self.error(entry, format!("unknown texture '{}'", name)))?;
+        if !self.resolving.insert(name.clone()) {
+            return Err(self.error(entry, format!("texture '{}' refers to itself", name)));
+        }
+
+        let texture_entry = format!("textures.{}", name);
+        let texture = match spec {
+            TextureSpec::Solid { color } => SolidColor::new(vec3(*color)),
+            TextureSpec::Checker { odd, even } => {
+                let odd = self.texture(odd, &texture_entry)?;
+                let even = self.texture(even, &texture_entry)?;
+                Checker::new(odd, even)
+            }
+            TextureSpec::Noise { scale, seed } => {
+                Noise::new(*scale, &mut Rng::seed_from_u64(*seed))
+            }
+            TextureSpec::Image { path } => {
+                let path = self.dir.join(path);
+                Image::open(&path).map_err(|err| {
+                    self.error(
+                        &texture_entry,
+                        format!("could not load image '{}': {}", path.display(), err),
+                    )
+                })?
+            }
+        };
+
+        self.resolving.remove(name);
+        self.textures.insert(name.clone(), texture.clone());
+        Ok(texture)
+    }
+
+    fn material(
+        &mut self,
+        material: &MaterialSpec,
+        entry: &str,
+    ) -> Result<SharedMaterial, SceneError> {
+        Ok(match material {
+            MaterialSpec::Lambertian { albedo } => Lambertian::new(self.texture(albedo, entry)?),
+            MaterialSpec::Metal { albedo, fuzz } => Metal::new(vec3(*albedo), *fuzz),
+            MaterialSpec::Conductor {
+                f0,
+                eta,
+                k,
+                roughness,
+            } => {
+                let fresnel = match (f0, eta, k) {
+                    (Some(f0), None, None) => ConductorFresnel::Schlick(vec3(*f0)),
+                    (None, Some(eta), Some(k)) => ConductorFresnel::Complex {
+                        eta: vec3(*eta),
+                        k: vec3(*k),
+                    },
+                    _ => return Err(self.error(entry, "expected either f0, or eta and k")),
+                };
+                let (roughness_x, roughness_y) = self.roughness(roughness, entry)?;
+                Metal::conductor(fresnel, roughness_x, roughness_y)
+            }
+            MaterialSpec::Dielectric {
+                ior,
+                roughness,
+                absorption,
+                transmittance,
+                distance,
+            } => {
+                let (roughness_x, roughness_y) = self.roughness(roughness, entry)?;
+                let absorption = match (absorption, transmittance) {
+                    (None, None) if distance.is_none() => Color::zero(),
+                    (Some(absorption), None) if distance.is_none() => vec3(*absorption),
+                    (None, Some(transmittance)) => {
+                        let distance = distance.unwrap_or(1.0);
+                        if !transmittance.iter().all(|t| (0.0..=1.0).contains(t)) || distance <= 0.0
+                        {
+                            return Err(self.error(
+                                entry,
+                                "transmittance must be between 0 and 1 over a positive distance",
+                            ));
+                        }
+                        Dielectric::absorption_for(vec3(*transmittance), distance)
+                    }
+                    _ => {
+                        return Err(self.error(
+                            entry,
+                            "expected either absorption, or transmittance and distance",
+                        ))
+                    }
+                };
+                if absorption.x < 0.0 || absorption.y < 0.0 || absorption.z < 0.0 {
+                    return Err(self.error(entry, "absorption must be at least 0"));
+                }
+                let ior = ior.ior();
+                let visible = [WAVELENGTH_MIN, REFERENCE_WAVELENGTH, WAVELENGTH_MAX];
+                if !visible.iter().all(|&l| ior.at(Some(l)) > 0.0) {
+                    return Err(self.error(entry, "ior must be positive at visible wavelengths"));
+                }
+                Dielectric::dispersive(ior, roughness_x, roughness_y, absorption)
+            }
+            MaterialSpec::DiffuseLight { emit } => DiffuseLight::new(self.texture(emit, entry)?),
+            MaterialSpec::Isotropic { albedo } => Isotropic::new(self.texture(albedo, entry)?),
+            MaterialSpec::Principled {
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                clearcoat,
+                sheen,
+                transmission,
+                ior,
+            } => {
+                let defaults = Principled::default();
+                let mut texture = |texture: &Option<TextureRef>, default: SharedTexture| {
+                    texture
+                        .as_ref()
+                        .map_or(Ok(default), |texture| self.texture(texture, entry))
+                };
+                let material = Principled {
+                    base_color: texture(base_color, defaults.base_color)?,
+                    metallic: texture(metallic, defaults.metallic)?,
+                    roughness: texture(roughness, defaults.roughness)?,
+                    specular: texture(specular, defaults.specular)?,
+                    clearcoat: texture(clearcoat, defaults.clearcoat)?,
+                    sheen: texture(sheen, defaults.sheen)?,
+                    transmission: texture(transmission, defaults.transmission)?,
+                    ior: ior.unwrap_or(defaults.ior),
+                };
+                if material.ior <= 0.0 {
+                    return Err(self.error(entry, "ior must be positive"));
+                }
+                Arc::new(material)
+            }
+        })
+    }
+
+    fn roughness(&self, roughness: &RoughnessSpec, entry: &str) -> Result<(f64, f64), SceneError> {
+        let (roughness_x, roughness_y) = roughness.pair();
+        if !(0.0..=1.0).contains(&roughness_x) || !(0.0..=1.0).contains(&roughness_y) {
+            return Err(self.error(entry, "roughness must be between 0 and 1"));
+        }
+        Ok((roughness_x, roughness_y))
+    }
+
+    fn named_material(&self, name: &str, entry: &str) -> Result<SharedMaterial, SceneError> {
+        self.materials
+            .get(name)
+            .cloned()
+            .ok_or_else(|| self.error(entry, format!("unknown material '{}'", name)))
+    }
+
+    fn object(&mut self, object: &ObjectSpec, entry: &str) -> Result<SharedHittable, SceneError> {
+        Ok(match object {
+            ObjectSpec::Sphere {
+                center,
+                radius,
+                material,
+            } => Sphere::new(
+                vec3(*center),
+                self.positive("radius", *radius, entry)?,
+                self.named_material(material, entry)?,
+            ),
+            ObjectSpec::MovingSphere {
+                center0,
+                center1,
+                time0,
+                time1,
+                radius,
+                material,
+            } => {
+                self.ordered(["time0", "time1"], (*time0, *time1), entry)?;
+                MovingSphere::new(
+                    vec3(*center0),
+                    vec3(*center1),
+                    *time0,
+                    *time1,
+                    self.positive("radius", *radius, entry)?,
+                    self.named_material(material, entry)?,
+                )
+            }
+            ObjectSpec::RectXy {
+                x0,
+                x1,
+                y0,
+                y1,
+                k,
+                material,
+            } => {
+                self.ordered(["x0", "x1"], (*x0, *x1), entry)?;
+                self.ordered(["y0", "y1"], (*y0, *y1), entry)?;
+                Rect2D::new_xy(
+                    *x0,
+                    *x1,
+                    *y0,
+                    *y1,
+                    *k,
+                    self.named_material(material, entry)?,
+                )
+            }
+            ObjectSpec::RectXz {
+                x0,
+                x1,
+                z0,
+                z1,
+                k,
+                material,
+            } => {
+                self.ordered(["x0", "x1"], (*x0, *x1), entry)?;
+                self.ordered(["z0", "z1"], (*z0, *z1), entry)?;
+                Rect2D::new_xz(
+                    *x0,
+                    *x1,
+                    *z0,
+                    *z1,
+                    *k,
+                    self.named_material(material, entry)?,
+                )
+            }
+            ObjectSpec::RectYz {
+                y0,
+                y1,
+                z0,
+                z1,
+                k,
+                material,
+            } => {
+                self.ordered(["y0", "y1"], (*y0, *y1), entry)?;
+                self.ordered(["z0", "z1"], (*z0, *z1), entry)?;
+                Rect2D::new_yz(
+                    *y0,
+                    *y1,
+                    *z0,
+                    *z1,
+                    *k,
+                    self.named_material(material, entry)?,
+                )
+            }
+            ObjectSpec::Cube { min, max, material } => Cube::new(
+                vec3(*min),
+                vec3(*max),
+                self.named_material(material, entry)?,
+            ),
+            ObjectSpec::Triangle {
+                vertices,
+                normals,
+                uvs,
+                material,
+            } => {
+                let uvs = uvs.unwrap_or([[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]);
+                Triangle::with_attributes(
+                    vertices.map(vec3),
+                    normals.map(|n| n.map(|v| vec3(v).normalized())),
+                    uvs.map(|uv| (uv[0], uv[1])),
+                    self.named_material(material, entry)?,
+                )
+            }
+            ObjectSpec::Mesh { path, material } => {
+                let material = match material {
+                    Some(name) => self.named_material(name, entry)?,
+                    None => Lambertian::new(SolidColor::new(Color::full(0.5))),
+                };
+                load_obj(self.dir.join(path), material)
+                    .map_err(|err| self.error(entry, err.to_string()))?
+            }
+            ObjectSpec::ConstantMedium {
+                boundary,
+                density,
+                albedo,
+            } => {
+                let density = self.positive("density", *density, entry)?;
+                let boundary = self.object(boundary, &format!("{}.boundary", entry))?;
+                ConstantMedium::new(boundary, density, self.texture(albedo, entry)?)
+            }
+            ObjectSpec::Transform {
+                object,
+                scale,
+                rotate,
+                translate,
+            } => {
+                let object = self.object(object, &format!("{}.object", entry))?;
+
+                let mut matrix = match scale {
+                    Some(ScaleSpec::Uniform(s)) => Mat4::scale(&Vec3::full(*s)),
+                    Some(ScaleSpec::PerAxis(s)) => Mat4::scale(&vec3(*s)),
+                    None => Mat4::identity(),
+                };
+                if let Some(r) = rotate {
+                    matrix = Mat4::rotate(&vec3(r.axis), r.degrees) * matrix;
+                }
+                if let Some(t) = translate {
+                    matrix = Mat4::translate(&vec3(*t)) * matrix;
+                }
+                if matrix.inverse().is_none() {
+                    return Err(self.error(entry, "transform is not invertible"));
+                }
+
+                Transform::new(object, matrix)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(src: &str) -> Result<Scene, SceneError> {
+        Scene::parse(src, Path::new("test.toml"))
+    }
+
+    fn error_message(src: &str) -> String {
+        match parse(src) {
+            Ok(_) => panic!("expected the scene to be rejected"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    const CAMERA: &str = "
+        [camera]
+        look_from = [13, 2, 3]
+        look_at = [0, 0, 0]
+        vfov = 20
+    ";
+
+    #[test]
+    fn test_parse_scene() {
+        let src = format!(
+            r#"
+            background = [0.7, 0.8, 1.0]
+            {}
+
+            [render]
+            image_width = 200
+
+            [textures.checker]
+            type = "checker"
+            odd = [0.2, 0.3, 0.1]
+            even = "white"
+
+            [textures.white]
+            type = "solid"
+            color = [0.9, 0.9, 0.9]
+
+            [materials.ground]
+            type = "lambertian"
+            albedo = "checker"
+
+            [materials.glass]
+            type = "dielectric"
+            ior = 1.5
+
+            [materials.crown]
+            type = "dielectric"
+            ior = {{ sellmeier_b = [1.0396, 0.2318, 1.0105], sellmeier_c = [0.0060, 0.0200, 103.56] }}
+
+            [materials.prism]
+            type = "dielectric"
+            ior = {{ cauchy = [1.5046, 0.0042] }}
+
+            [materials.frosted]
+            type = "dielectric"
+            ior = 1.5
+            roughness = 0.3
+            transmittance = [0.9, 0.6, 0.3]
+            distance = 2
+
+            [materials.gold]
+            type = "conductor"
+            eta = [0.143, 0.374, 1.442]
+            k = [3.983, 2.385, 1.603]
+            roughness = [0.1, 0.4]
+
+            [materials.brass]
+            type = "conductor"
+            f0 = [0.9, 0.7, 0.4]
+            roughness = 0.2
+
+            [materials.painted]
+            type = "principled"
+            base_color = "checker"
+            metallic = 0.2
+            roughness = "checker"
+            clearcoat = 1
+
+            [[objects]]
+            type = "sphere"
+            center = [0, -1000, 0]
+            radius = 1000
+            material = "ground"
+
+            [[objects]]
+            type = "transform"
+            translate = [0, 1, 0]
+            rotate = {{ axis = [0, 1, 0], degrees = 45 }}
+            object = {{ type = "cube", min = [0, 0, 0], max = [1, 1, 1], material = "glass" }}
+            "#,
+            CAMERA
+        );
+        let scene = parse(&src).unwrap();
+
+        assert_eq!(scene.objects.len(), 2);
+        assert_eq!(scene.image_width, 200);
+        assert_eq!(scene.samples_per_pixel, 100);
+        assert!((scene.camera.focus_dist - Vec3::new(13.0, 2.0, 3.0).mag()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_errors_name_entry() {
+        let msg = error_message(&format!(
+            r#"{}
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "missing"
+            "#,
+            CAMERA
+        ));
+        assert!(
+            msg.contains("objects[0]") && msg.contains("'missing'"),
+            "{}",
+            msg
+        );
+
+        let msg = error_message(&format!(
+            r#"{}
+            [materials.floor]
+            type = "lambertian"
+            albedo = "nope"
+            "#,
+            CAMERA
+        ));
+        assert!(
+            msg.contains("materials.floor") && msg.contains("'nope'"),
+            "{}",
+            msg
+        );
+
+        let msg = error_message(&format!(
+            r#"{}
+            [textures.a]
+            type = "checker"
+            odd = "a"
+            even = [1, 1, 1]
+
+            [materials.floor]
+            type = "lambertian"
+            albedo = "a"
+            "#,
+            CAMERA
+        ));
+        assert!(msg.contains("textures.a"), "{}", msg);
+
+        let msg = error_message(&format!(
+            r#"{}
+            [materials.m]
+            type = "conductor"
+            f0 = [1, 1, 1]
+            eta = [1, 1, 1]
+            "#,
+            CAMERA
+        ));
+        assert!(msg.contains("materials.m"), "{}", msg);
+
+        let msg = error_message(&format!(
+            r#"{}
+            [materials.glass]
+            type = "dielectric"
+            ior = 1.5
+            absorption = [0.1, 0.1, 0.1]
+            transmittance = [0.5, 0.5, 0.5]
+            "#,
+            CAMERA
+        ));
+        assert!(msg.contains("materials.glass"), "{}", msg);
+    }
+
+    #[test]
+    fn test_rejects_non_positive_values() {
+        for (settings, field) in [
+            ("image_width = 0", "image_width"),
+            ("samples_per_pixel = -1", "samples_per_pixel"),
+            ("aspect_ratio = nan", "aspect_ratio"),
+            ("max_depth = -1", "max_depth"),
+        ] {
+            let msg = error_message(&format!("{}\n[render]\n{}\n", CAMERA, settings));
+            assert!(msg.contains("render") && msg.contains(field), "{}", msg);
+        }
+
+        let msg = error_message(&format!(
+            r#"{}
+            [materials.m]
+            type = "lambertian"
+            albedo = [1, 1, 1]
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "m"
+
+            [[objects]]
+            type = "constant_medium"
+            density = 0
+            albedo = [1, 1, 1]
+            boundary = {{ type = "sphere", center = [0, 0, 0], radius = 1, material = "m" }}
+            "#,
+            CAMERA
+        ));
+        assert!(
+            msg.contains("objects[1]") && msg.contains("density"),
+            "{}",
+            msg
+        );
+
+        let msg = error_message(&format!(
+            "{}\n[[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = -1\nmaterial = \"m\"\n",
+            CAMERA
+        ));
+        assert!(
+            msg.contains("objects[0]") && msg.contains("radius"),
+            "{}",
+            msg
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_camera_and_bounds() {
+        for (camera, field) in [
+            ("vfov = 0", "vfov"),
+            ("vfov = 180", "vfov"),
+            ("vfov = 20\naperture = -0.1", "aperture"),
+        ] {
+            let msg = error_message(&format!(
+                "[camera]\nlook_from = [13, 2, 3]\nlook_at = [0, 0, 0]\n{}\n",
+                camera
+            ));
+            assert!(msg.contains("camera") && msg.contains(field), "{}", msg);
+        }
+
+        for (object, field) in [
+            (
+                "type = \"rect_xy\"\nx0 = 1\nx1 = 0\ny0 = 0\ny1 = 1\nk = 0",
+                "x0",
+            ),
+            (
+                "type = \"rect_xz\"\nx0 = 0\nx1 = 1\nz0 = 2\nz1 = 2\nk = 0",
+                "z0",
+            ),
+            (
+                "type = \"rect_yz\"\ny0 = 0\ny1 = -1\nz0 = 0\nz1 = 1\nk = 0",
+                "y0",
+            ),
+            (
+                "type = \"moving_sphere\"\ncenter0 = [0, 0, 0]\ncenter1 = [0, 1, 0]\ntime0 = 1\ntime1 = 0\nradius = 1",
+                "time0",
+            ),
+        ] {
+            let msg = error_message(&format!(
+                "{}\n[[objects]]\n{}\nmaterial = \"m\"\n",
+                CAMERA,
+                object
+            ));
+            assert!(msg.contains("objects[0]") && msg.contains(field), "{}", msg);
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let msg = error_message("[camera]\nlook_from = [13, 2, 3]\n");
+        assert!(msg.contains("look_at"), "{}", msg);
+
+        let msg = error_message(&format!(
+            "{}\n[[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nmaterial = \"m\"\ncolour = 1\n",
+            CAMERA
+        ));
+        assert!(msg.contains("colour"), "{}", msg);
+    }
+}
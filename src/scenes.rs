@@ -0,0 +1,470 @@
+use crate::aarect::Rect2D;
+use crate::bvh::{BvhSplit, LinearBvh};
+use crate::constant_medium::ConstantMedium;
+use crate::cube::Cube;
+use crate::hittable::{Hittable, SharedHittable};
+use crate::material::*;
+use crate::matrix::Mat4;
+use crate::moving_sphere::MovingSphere;
+use crate::obj::load_obj;
+use crate::scene::{CameraSettings, Scene};
+use crate::sphere::Sphere;
+use crate::texture::*;
+use crate::transform::{RotateY, Transform, Translate};
+use crate::triangle::Triangle;
+use crate::util::*;
+use crate::vec3::*;
+use rand::SeedableRng;
+use std::sync::Arc;
+
+/// Number of built-in scenes, numbered from 1.
+pub const SCENE_COUNT: i32 = 10;
+
+/// Returns built-in scene number `scene`, or `None` if there is no such scene. `seed` seeds
+/// the scenes that are randomly generated.
+pub fn builtin_scene(scene: i32, seed: u64) -> Option<Scene> {
+    let mut rng = Rng::seed_from_u64(seed);
+    let sky = Color::new(0.7, 0.8, 1.0);
+    let default_camera = CameraSettings {
+        look_from: Point3::new(13.0, 2.0, 3.0),
+        look_at: Point3::zero(),
+        vup: Vec3::new(0.0, 1.0, 0.0),
+        vfov: 20.0,
+        aperture: 0.0,
+        focus_dist: 10.0,
+        time0: 0.0,
+        time1: 1.0,
+    };
+    let cornell_camera = CameraSettings {
+        look_from: Point3::new(278.0, 278.0, -800.0),
+        look_at: Point3::new(278.0, 278.0, 0.0),
+        vfov: 40.0,
+        ..default_camera
+    };
+
+    let scene = match scene {
+        1 => Scene::new(
+            random_scene(&mut rng),
+            sky,
+            CameraSettings {
+                aperture: 0.1,
+                ..default_camera
+            },
+        ),
+        2 => Scene::new(two_spheres(), sky, default_camera),
+        3 => Scene::new(two_perlin_spheres(&mut rng), sky, default_camera),
+        4 => Scene::new(earth(), sky, default_camera),
+        5 => Scene {
+            samples_per_pixel: 400,
+            ..Scene::new(
+                simple_light(&mut rng),
+                Color::zero(),
+                CameraSettings {
+                    look_from: Point3::new(26.0, 3.0, 6.0),
+                    look_at: Point3::new(0.0, 2.0, 0.0),
+                    ..default_camera
+                },
+            )
+        },
+        7 => Scene::new(
+            triangles(),
+            sky,
+            CameraSettings {
+                look_at: Point3::new(0.0, 1.0, 0.0),
+                ..default_camera
+            },
+        ),
+        8 => Scene::new(
+            obj_model(),
+            sky,
+            CameraSettings {
+                look_at: Point3::new(0.0, 1.0, 0.0),
+                ..default_camera
+            },
+        ),
+        9 => Scene {
+            aspect_ratio: 1.0,
+            image_width: 600,
+            samples_per_pixel: 200,
+            ..Scene::new(cornell_smoke(), Color::zero(), cornell_camera)
+        },
+        10 => Scene {
+            aspect_ratio: 1.0,
+            image_width: 800,
+            samples_per_pixel: 1000,
+            ..Scene::new(
+                final_scene(&mut rng),
+                Color::zero(),
+                CameraSettings {
+                    look_from: Point3::new(478.0, 278.0, -600.0),
+                    ..cornell_camera
+                },
+            )
+        },
+        6 => Scene {
+            aspect_ratio: 1.0,
+            image_width: 600,
+            samples_per_pixel: 200,
+            ..Scene::new(cornell_box(), Color::zero(), cornell_camera)
+        },
+        _ => return None,
+    };
+    Some(scene)
+}
+
+fn random_scene(rng: &mut Rng) -> Vec<SharedHittable> {
+    let mut world = Vec::new();
+
+    let checker = Checker::new(
+        SolidColor::new(Color::new(0.2, 0.3, 0.1)),
+        SolidColor::new(Color::new(0.9, 0.9, 0.9)),
+    );
+
+    let ground_mat = Lambertian::new(checker);
+    let ground = Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, ground_mat);
+    world.push(ground);
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = rand(rng);
+            let center = Point3::new(a as f64 + 0.9 * rand(rng), 0.2, b as f64 + 0.9 * rand(rng));
+
+            if (center - Point3::new(4.0, 0.2, 0.0)).mag() > 0.9 {
+                let sphere_mat: SharedMaterial = if choose_mat < 0.8 {
+                    // diffuse
+                    let albedo = Color::random(rng) * Color::random(rng);
+                    let mat = Lambertian::new(SolidColor::new(albedo));
+                    let center2 = center + Vec3::new(0.0, rand_range(0.0, 0.5, rng), 0.0);
+                    world.push(MovingSphere::new(
+                        center,
+                        center2,
+                        0.0,
+                        1.0,
+                        0.2,
+                        mat.clone(),
+                    ));
+                    mat
+                } else if choose_mat < 0.95 {
+                    // metal
+                    let albedo = Color::random_range(0.5, 1.0, rng);
+                    let fuzz = rand_range(0.0, 0.5, rng);
+                    Metal::new(albedo, fuzz)
+                } else {
+                    // glass
+                    Dielectric::new(1.5)
+                };
+
+                let sphere = Sphere::new(center, 0.2, sphere_mat);
+                world.push(sphere);
+            }
+        }
+    }
+
+    world
+}
+
+fn two_spheres() -> Vec<SharedHittable> {
+    let checker = Checker::new(
+        SolidColor::new(Color::new(0.2, 0.3, 0.1)),
+        SolidColor::new(Color::new(0.9, 0.9, 0.9)),
+    );
+
+    vec![
+        Sphere::new(
+            Point3::new(0.0, -10.0, 0.0),
+            10.0,
+            Lambertian::new(checker.clone()),
+        ),
+        Sphere::new(Point3::new(0.0, 10.0, 0.0), 10.0, Lambertian::new(checker)),
+    ]
+}
+
+fn two_perlin_spheres(rng: &mut Rng) -> Vec<SharedHittable> {
+    let noise = Noise::new(4.0, rng);
+
+    vec![
+        Sphere::new(
+            Point3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Lambertian::new(noise.clone()),
+        ),
+        Sphere::new(Point3::new(0.0, 2.0, 0.0), 2.0, Lambertian::new(noise)),
+    ]
+}
+
+fn earth() -> Vec<SharedHittable> {
+    let earth_texture = Image::new("./earthmap.jpg");
+    let earth_surface = Lambertian::new(earth_texture);
+
+    vec![Sphere::new(Point3::zero(), 2.0, earth_surface)]
+}
+
+fn simple_light(rng: &mut Rng) -> Vec<SharedHittable> {
+    let noise = Noise::new(4.0, rng);
+    let difflight = DiffuseLight::new(SolidColor::new(Color::new(4.0, 4.0, 4.0)));
+
+    vec![
+        Sphere::new(
+            Point3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Lambertian::new(noise.clone()),
+        ),
+        Sphere::new(Point3::new(0.0, 2.0, 0.0), 2.0, Lambertian::new(noise)),
+        Rect2D::new_xy(3.0, 5.0, 1.0, 3.0, -2.0, difflight),
+    ]
+}
+
+fn cornell_box() -> Vec<SharedHittable> {
+    let red = Lambertian::new(SolidColor::new(Color::new(0.65, 0.05, 0.05)));
+    let white = Lambertian::new(SolidColor::new(Color::new(0.73, 0.73, 0.73)));
+    let green = Lambertian::new(SolidColor::new(Color::new(0.12, 0.45, 0.15)));
+    let light = DiffuseLight::new(SolidColor::new(Color::new(15.0, 15.0, 15.0)));
+
+    vec![
+        Rect2D::new_yz(0.0, 555.0, 0.0, 555.0, 555.0, green),
+        Rect2D::new_yz(0.0, 555.0, 0.0, 555.0, 0.0, red),
+        Rect2D::new_xz(213.0, 343.0, 227.0, 332.0, 554.0, light),
+        Rect2D::new_xz(0.0, 555.0, 0.0, 555.0, 0.0, white.clone()),
+        Rect2D::new_xz(0.0, 555.0, 0.0, 555.0, 555.0, white.clone()),
+        Rect2D::new_xy(0.0, 555.0, 0.0, 555.0, 555.0, white.clone()),
+        Translate::new(
+            RotateY::new(
+                Cube::new(
+                    Point3::zero(),
+                    Point3::new(165.0, 330.0, 165.0),
+                    white.clone(),
+                ),
+                15.0,
+            ),
+            Vec3::new(265.0, 0.0, 295.0),
+        ),
+        Translate::new(
+            RotateY::new(Cube::new(Point3::zero(), Point3::full(165.0), white), -18.0),
+            Vec3::new(130.0, 0.0, 65.0),
+        ),
+    ]
+}
+
+fn cornell_smoke() -> Vec<SharedHittable> {
+    let red = Lambertian::new(SolidColor::new(Color::new(0.65, 0.05, 0.05)));
+    let white = Lambertian::new(SolidColor::new(Color::new(0.73, 0.73, 0.73)));
+    let green = Lambertian::new(SolidColor::new(Color::new(0.12, 0.45, 0.15)));
+    let light = DiffuseLight::new(SolidColor::new(Color::new(7.0, 7.0, 7.0)));
+
+    let box1 = Translate::new(
+        RotateY::new(
+            Cube::new(
+                Point3::zero(),
+                Point3::new(165.0, 330.0, 165.0),
+                white.clone(),
+            ),
+            15.0,
+        ),
+        Vec3::new(265.0, 0.0, 295.0),
+    );
+    let box2 = Translate::new(
+        RotateY::new(
+            Cube::new(Point3::zero(), Point3::full(165.0), white.clone()),
+            -18.0,
+        ),
+        Vec3::new(130.0, 0.0, 65.0),
+    );
+
+    vec![
+        Rect2D::new_yz(0.0, 555.0, 0.0, 555.0, 555.0, green),
+        Rect2D::new_yz(0.0, 555.0, 0.0, 555.0, 0.0, red),
+        Rect2D::new_xz(113.0, 443.0, 127.0, 432.0, 554.0, light),
+        Rect2D::new_xz(0.0, 555.0, 0.0, 555.0, 0.0, white.clone()),
+        Rect2D::new_xz(0.0, 555.0, 0.0, 555.0, 555.0, white.clone()),
+        Rect2D::new_xy(0.0, 555.0, 0.0, 555.0, 555.0, white),
+        ConstantMedium::new(box1, 0.01, SolidColor::new(Color::zero())),
+        ConstantMedium::new(box2, 0.01, SolidColor::new(Color::one())),
+    ]
+}
+
+fn final_scene(rng: &mut Rng) -> Vec<SharedHittable> {
+    let ground = Lambertian::new(SolidColor::new(Color::new(0.48, 0.83, 0.53)));
+
+    let boxes_per_side = 20;
+    let mut boxes = Vec::new();
+    for i in 0..boxes_per_side {
+        for j in 0..boxes_per_side {
+            let w = 100.0;
+            let x0 = -1000.0 + i as f64 * w;
+            let z0 = -1000.0 + j as f64 * w;
+            let y1 = rand_range(1.0, 101.0, rng);
+            boxes.push(Cube::new(
+                Point3::new(x0, 0.0, z0),
+                Point3::new(x0 + w, y1, z0 + w),
+                ground.clone(),
+            ));
+        }
+    }
+
+    let mut world = vec![LinearBvh::from_objects(0.0, 1.0, boxes, BvhSplit::Sah)];
+
+    let light = DiffuseLight::new(SolidColor::new(Color::new(7.0, 7.0, 7.0)));
+    world.push(Rect2D::new_xz(123.0, 423.0, 147.0, 412.0, 554.0, light));
+
+    let center1 = Point3::new(400.0, 400.0, 200.0);
+    let center2 = center1 + Vec3::new(30.0, 0.0, 0.0);
+    let moving_sphere_material = Lambertian::new(SolidColor::new(Color::new(0.7, 0.3, 0.1)));
+    world.push(MovingSphere::new(
+        center1,
+        center2,
+        0.0,
+        1.0,
+        50.0,
+        moving_sphere_material,
+    ));
+
+    world.push(Sphere::new(
+        Point3::new(260.0, 150.0, 45.0),
+        50.0,
+        Dielectric::new(1.5),
+    ));
+    world.push(Sphere::new(
+        Point3::new(0.0, 150.0, 145.0),
+        50.0,
+        Metal::new(Color::new(0.8, 0.8, 0.9), 1.0),
+    ));
+
+    // A glass ball filled with blue subsurface-like haze
+    let boundary = Point3::new(360.0, 150.0, 145.0);
+    world.push(Sphere::new(boundary, 70.0, Dielectric::new(1.5)));
+    world.push(ConstantMedium::new(
+        Sphere::new(boundary, 70.0, Dielectric::new(1.5)),
+        0.2,
+        SolidColor::new(Color::new(0.2, 0.4, 0.9)),
+    ));
+
+    // Thin mist over the whole scene
+    world.push(ConstantMedium::new(
+        Sphere::new(Point3::zero(), 5000.0, Dielectric::new(1.5)),
+        0.0001,
+        SolidColor::new(Color::one()),
+    ));
+
+    let earth_material = Lambertian::new(Image::new("./earthmap.jpg"));
+    world.push(Sphere::new(
+        Point3::new(400.0, 200.0, 400.0),
+        100.0,
+        earth_material,
+    ));
+
+    let noise = Noise::new(0.1, rng);
+    world.push(Sphere::new(
+        Point3::new(220.0, 280.0, 300.0),
+        80.0,
+        Lambertian::new(noise),
+    ));
+
+    let white = Lambertian::new(SolidColor::new(Color::full(0.73)));
+    let spheres = (0..1000)
+        .map(|_| Sphere::new(Point3::random_range(0.0, 165.0, rng), 10.0, white.clone()))
+        .collect();
+    world.push(Translate::new(
+        RotateY::new(
+            LinearBvh::from_objects(0.0, 1.0, spheres, BvhSplit::Sah),
+            15.0,
+        ),
+        Vec3::new(-100.0, 270.0, 395.0),
+    ));
+
+    world
+}
+
+fn octahedron(
+    center: Point3,
+    radius: f64,
+    smooth: bool,
+    material: SharedMaterial,
+) -> Vec<SharedHittable> {
+    let dirs = [
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -1.0),
+        Vec3::new(-1.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 1.0),
+    ];
+    let up = Vec3::new(0.0, 1.0, 0.0);
+
+    let mut faces = Vec::new();
+    for i in 0..4 {
+        let (a, b) = (dirs[i], dirs[(i + 1) % 4]);
+        for pole in [up, -up] {
+            // Wind each face counter-clockwise when seen from outside
+            let n = if pole.y > 0.0 {
+                [a, b, pole]
+            } else {
+                [b, a, pole]
+            };
+            let vertices = [
+                center + radius * n[0],
+                center + radius * n[1],
+                center + radius * n[2],
+            ];
+            let uvs = [(0.0, 0.0), (1.0, 0.0), (0.5, 1.0)];
+            let normals = if smooth { Some(n) } else { None };
+            faces.push(Triangle::with_attributes(
+                vertices,
+                normals,
+                uvs,
+                material.clone(),
+            ));
+        }
+    }
+    faces
+}
+
+fn triangles() -> Vec<SharedHittable> {
+    let checker = Checker::new(
+        SolidColor::new(Color::new(0.2, 0.3, 0.1)),
+        SolidColor::new(Color::new(0.9, 0.9, 0.9)),
+    );
+
+    let mut world = vec![
+        Sphere::new(
+            Point3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Lambertian::new(checker),
+        ),
+        Triangle::new(
+            Point3::new(-3.0, 0.0, -3.0),
+            Point3::new(-3.0, 0.0, 3.0),
+            Point3::new(-5.0, 3.0, 0.0),
+            Metal::new(Color::new(0.8, 0.8, 0.9), 0.05),
+        ),
+    ];
+
+    let red = Lambertian::new(SolidColor::new(Color::new(0.65, 0.05, 0.05)));
+    world.extend(octahedron(
+        Point3::new(0.0, 1.0, -1.2),
+        1.0,
+        false,
+        red.clone(),
+    ));
+
+    // One smooth octahedron shared by several transformed instances
+    let smooth: Arc<dyn Hittable + Send + Sync> =
+        Arc::new(octahedron(Point3::zero(), 1.0, true, red));
+    world.push(Transform::instance(
+        &smooth,
+        Mat4::translate(&Vec3::new(0.0, 1.0, 1.2)),
+    ));
+    world.push(Transform::instance(
+        &smooth,
+        Mat4::translate(&Vec3::new(-2.0, 0.5, 2.5))
+            * Mat4::rotate(&Vec3::new(1.0, 0.0, 1.0), 30.0)
+            * Mat4::scale(&Vec3::new(0.5, 0.5, 0.5)),
+    ));
+
+    world
+}
+
+fn obj_model() -> Vec<SharedHittable> {
+    let default_material = Lambertian::new(SolidColor::new(Color::new(0.5, 0.5, 0.5)));
+    let model = load_obj("./models/icosphere.obj", default_material)
+        .unwrap_or_else(|err| panic!("Error loading model: {}", err));
+
+    vec![model]
+}
@@ -0,0 +1,185 @@
+use crate::util::Color;
+use std::sync::OnceLock;
+
+/// The range of wavelengths, in nanometres, that `sample_wavelength` draws from.
+pub const WAVELENGTH_MIN: f64 = 360.0;
+pub const WAVELENGTH_MAX: f64 = 830.0;
+
+/// Wavelength at which a glass's index of refraction is usually quoted, the helium d line.
+pub const REFERENCE_WAVELENGTH: f64 = 587.56;
+
+/// The number of wavelengths each camera sample follows, the hero and its companions.
+pub const WAVELENGTH_SAMPLES: usize = 4;
+
+/// Index of refraction, constant or varying with wavelength.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Ior {
+    Constant(f64),
+    /// Cauchy's equation, `a + b / λ²` with λ in micrometres
+    Cauchy {
+        a: f64,
+        b: f64,
+    },
+    /// The Sellmeier equation, `n² = 1 + Σ bᵢ λ² / (λ² - cᵢ)` with λ in micrometres
+    Sellmeier {
+        b: [f64; 3],
+        c: [f64; 3],
+    },
+}
+
+impl Ior {
+    /// Whether the index varies with wavelength, splitting white light into its colors.
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Ior::Constant(_))
+    }
+
+    /// The index at `wavelength` in nanometres, or at the reference wavelength for `None`.
+    pub fn at(&self, wavelength: Option<f64>) -> f64 {
+        let um = wavelength.unwrap_or(REFERENCE_WAVELENGTH) / 1000.0;
+        let um2 = um * um;
+        match *self {
+            Ior::Constant(n) => n,
+            Ior::Cauchy { a, b } => a + b / um2,
+            Ior::Sellmeier { b, c } => {
+                let sum: f64 = b.iter().zip(c).map(|(b, c)| b * um2 / (um2 - c)).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+}
+
+/// Piecewise Gaussian used by the CIE fit.
+fn lobe(wavelength: f64, mu: f64, sigma_below: f64, sigma_above: f64) -> f64 {
+    let sigma = if wavelength < mu {
+        sigma_below
+    } else {
+        sigma_above
+    };
+    let t = (wavelength - mu) / sigma;
+    (-0.5 * t * t).exp()
+}
+
+/// Linear sRGB of a single wavelength of unit power, from the CIE 1931 color matching
+/// functions (fit by Wyman et al., "Simple Analytic Approximations to the CIE XYZ Color
+/// Matching Functions"). Colors outside the sRGB gamut have negative components.
+fn wavelength_to_rgb(wavelength: f64) -> Color {
+    let l = wavelength;
+    let x = 1.056 * lobe(l, 599.8, 37.9, 31.0) + 0.362 * lobe(l, 442.0, 16.0, 26.7)
+        - 0.065 * lobe(l, 501.1, 20.4, 26.2);
+    let y = 0.821 * lobe(l, 568.8, 46.9, 40.5) + 0.286 * lobe(l, 530.9, 16.3, 31.1);
+    let z = 1.217 * lobe(l, 437.0, 11.8, 36.0) + 0.681 * lobe(l, 459.0, 26.0, 13.8);
+    Color::new(
+        3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+        -0.9692660 * x + 1.8760108 * y + 0.0415560 * z,
+        0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+    )
+}
+
+/// The integral of `wavelength_to_rgb` over the sampled range, which white light sums to.
+fn white() -> Color {
+    static WHITE: OnceLock<Color> = OnceLock::new();
+    *WHITE.get_or_init(|| {
+        let steps = (WAVELENGTH_MAX - WAVELENGTH_MIN) as usize;
+        (0..steps)
+            .map(|i| wavelength_to_rgb(WAVELENGTH_MIN + i as f64 + 0.5))
+            .fold(Color::zero(), |sum, rgb| sum + rgb)
+    })
+}
+
+fn wavelength_pdf(wavelength: f64) -> f64 {
+    0.0039398042 / (0.0072 * (wavelength - 538.0)).cosh().powi(2)
+}
+
+/// Draws a wavelength in nanometres, more often where the eye is most sensitive (pbrt's
+/// visible wavelength sampling).
+fn sample_visible(u: f64) -> f64 {
+    (538.0 - 138.888889 * (0.85691062 - 1.82750197 * u).atanh())
+        .clamp(WAVELENGTH_MIN, WAVELENGTH_MAX)
+}
+
+/// The wavelengths a camera sample follows, by hero wavelength sampling (Wilkie et al.): the
+/// hero is drawn from `u`, and its companions from `u` rotated by even steps around [0, 1),
+/// so that together they cover the spectrum. Paths choose their directions at the hero and
+/// weight each wavelength by how likely it was to scatter the same way; where the direction
+/// itself depends on the wavelength, only the hero can follow it and the companions are
+/// terminated, as in pbrt-v4.
+#[derive(Debug, Copy, Clone)]
+pub struct SampledWavelengths {
+    lambda: [f64; WAVELENGTH_SAMPLES],
+    /// Densities the wavelengths were drawn with
+    pdf: [f64; WAVELENGTH_SAMPLES],
+}
+
+impl SampledWavelengths {
+    pub fn sample(u: f64) -> SampledWavelengths {
+        let lambda = std::array::from_fn(|i| {
+            sample_visible((u + i as f64 / WAVELENGTH_SAMPLES as f64).fract())
+        });
+        SampledWavelengths {
+            lambda,
+            pdf: lambda.map(wavelength_pdf),
+        }
+    }
+
+    /// The wavelength in nanometres that scattering directions are chosen at, the first.
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    /// The hero followed by its companions, in nanometres.
+    pub fn lambdas(&self) -> [f64; WAVELENGTH_SAMPLES] {
+        self.lambda
+    }
+
+    /// Converts `radiance`, RGB light filtered to each of the wavelengths, to RGB: the color of
+    /// each wavelength divided by the density of drawing it, so that light that is white at
+    /// every wavelength averages to white over the wavelengths drawn.
+    ///
+    /// Wavelengths outside the sRGB gamut have negative components, so single samples can be
+    /// negative; this is intentional, as clamping them would shift the average color. Their
+    /// mean converges to the in-gamut color, and LDR output clamps what remains.
+    pub fn to_rgb(&self, radiance: &[Color; WAVELENGTH_SAMPLES]) -> Color {
+        let sum = self
+            .lambda
+            .iter()
+            .zip(self.pdf)
+            .zip(radiance)
+            .fold(Color::zero(), |sum, ((&lambda, pdf), &radiance)| {
+                sum + wavelength_to_rgb(lambda) * radiance / pdf
+            });
+        sum / (WAVELENGTH_SAMPLES as f64 * white())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wavelengths_average_to_white() {
+        let n = 1 << 12;
+        let mut mean = Color::zero();
+        let mut hero_mean = Color::zero();
+        for i in 0..n {
+            let wavelengths = SampledWavelengths::sample((i as f64 + 0.5) / n as f64);
+            mean += wavelengths.to_rgb(&[Color::one(); WAVELENGTH_SAMPLES]) / n as f64;
+            // The hero standing in for all of them
+            let mut hero = [Color::zero(); WAVELENGTH_SAMPLES];
+            hero[0] = Color::full(WAVELENGTH_SAMPLES as f64);
+            hero_mean += wavelengths.to_rgb(&hero) / n as f64;
+        }
+        assert!((mean - Color::one()).mag() < 0.01, "{:?}", mean);
+        assert!((hero_mean - Color::one()).mag() < 0.01, "{:?}", hero_mean);
+
+        // The companions spread across the spectrum, and red light refracts less than blue
+        let wavelengths = SampledWavelengths::sample(0.95);
+        let (red, blue) = (wavelengths.hero(), wavelengths.lambda[1]);
+        assert!(wavelengths.lambda[1..].windows(2).all(|w| w[0] < w[1]));
+        let glass = Ior::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
+        };
+        assert!((glass.at(None) - 1.5168).abs() < 1e-3);
+        assert!(glass.at(Some(red)) < glass.at(Some(blue)));
+    }
+}
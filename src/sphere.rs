@@ -3,7 +3,7 @@ use crate::hittable::{HitRecord, Hittable, SharedHittable};
 use crate::material::SharedMaterial;
 use crate::ray::Ray;
 use crate::util::{Point3, Time};
-use crate::vec3::{dot, Vec3};
+use crate::vec3::{dot, orthonormal_basis, sample_unit_vector, Vec3};
 use std::f64::consts::PI;
 
 pub struct Sphere {
@@ -13,6 +13,7 @@ pub struct Sphere {
 }
 
 impl Sphere {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(center: Point3, radius: f64, material: SharedMaterial) -> SharedHittable {
         Box::new(Sphere {
             center,
@@ -67,7 +68,7 @@ impl Hittable for Sphere {
             root,
             u,
             v,
-            &r,
+            r,
             &outward_normal,
             self.material.clone(),
         ))
@@ -77,4 +78,43 @@ impl Hittable for Sphere {
         let rvec = Vec3::full(self.radius);
         Some(AABB::new(self.center - rvec, self.center + rvec))
     }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn sample_direction(&self, origin: &Point3, u: (f64, f64)) -> Vec3 {
+        let to_center = self.center - origin;
+        let distance_squared = to_center.mag_squared();
+        if distance_squared <= self.radius.powi(2) {
+            // From inside, any point of the sphere is visible
+            return self.center + self.radius * sample_unit_vector(u) - origin;
+        }
+
+        // Uniformly within the cone of directions that hit the sphere
+        let cos_theta_max = (1.0 - self.radius.powi(2) / distance_squared).sqrt();
+        let z = 1.0 + u.0 * (cos_theta_max - 1.0);
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.1;
+        let w = to_center.normalized();
+        let (s, t) = orthonormal_basis(&w);
+        r * phi.cos() * s + r * phi.sin() * t + z * w
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let rec = match self.hit(&Ray::new(*origin, *direction, 0.0), 0.001, f64::INFINITY) {
+            Some(rec) => rec,
+            None => return 0.0,
+        };
+
+        let distance_squared = (self.center - origin).mag_squared();
+        if distance_squared <= self.radius.powi(2) {
+            let area = 4.0 * PI * self.radius.powi(2);
+            let cosine = dot(&rec.normal, direction).abs() / direction.mag();
+            return rec.t * rec.t * direction.mag_squared() / (cosine * area);
+        }
+
+        let cos_theta_max = (1.0 - self.radius.powi(2) / distance_squared).sqrt();
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
 }
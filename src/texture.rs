@@ -1,7 +1,8 @@
 use crate::perlin::Perlin;
 use crate::util::*;
 use image::io::Reader as ImageReader;
-use image::{Pixel, RgbImage};
+use image::{ImageResult, Pixel, RgbImage};
+use std::path::Path;
 use std::sync::Arc;
 
 pub trait Texture {
@@ -15,6 +16,7 @@ pub struct SolidColor {
 }
 
 impl SolidColor {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(color: Color) -> SharedTexture {
         Arc::new(SolidColor { color })
     }
@@ -32,6 +34,7 @@ pub struct Checker {
 }
 
 impl Checker {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(odd: SharedTexture, even: SharedTexture) -> SharedTexture {
         Arc::new(Checker { odd, even })
     }
@@ -51,9 +54,10 @@ pub struct Noise {
 }
 
 impl Noise {
-    pub fn new(scale: f64) -> SharedTexture {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(scale: f64, rng: &mut Rng) -> SharedTexture {
         Arc::new(Noise {
-            noise: Perlin::new(),
+            noise: Perlin::new(rng),
             scale,
         })
     }
@@ -70,13 +74,14 @@ pub struct Image {
 }
 
 impl Image {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(filename: &str) -> SharedTexture {
-        let image = ImageReader::open(filename)
-            .unwrap()
-            .decode()
-            .unwrap()
-            .to_rgb8();
-        Arc::new(Image { image })
+        Self::open(filename).unwrap()
+    }
+
+    pub fn open(filename: impl AsRef<Path>) -> ImageResult<SharedTexture> {
+        let image = ImageReader::open(filename)?.decode()?.to_rgb8();
+        Ok(Arc::new(Image { image }))
     }
 }
 
@@ -86,8 +91,8 @@ impl Texture for Image {
         let vn = 1.0 - v.clamp(0.0, 1.0);
 
         // Clamp coordinates
-        let i = ((un * self.image.width() as f64) as u32).max(self.image.width() - 1);
-        let j = ((vn * self.image.height() as f64) as u32).max(self.image.height() - 1);
+        let i = ((un * self.image.width() as f64) as u32).min(self.image.width() - 1);
+        let j = ((vn * self.image.height() as f64) as u32).min(self.image.height() - 1);
 
         let color_scale = 1.0 / 255.0;
         let pixel = self.image.get_pixel(i, j).to_rgb();
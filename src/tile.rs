@@ -0,0 +1,122 @@
+/// Order in which the tiles of an image are handed to the render threads.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TileOrder {
+    /// Left to right, top to bottom.
+    Scanline,
+    /// Outwards from the center of the image, where the subject usually is.
+    Spiral,
+}
+
+/// A rectangular block of pixels that is rendered as one unit of work. `x` and `y` are the
+/// top left pixel, counting rows from the top.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Splits a `width` x `height` image into tiles of at most `tile_size` pixels square, in the
+/// given order.
+pub fn tiles(width: u32, height: u32, tile_size: u32, order: TileOrder) -> Vec<Tile> {
+    let columns = width.div_ceil(tile_size);
+    let rows = height.div_ceil(tile_size);
+
+    let mut grid: Vec<(u32, u32)> = (0..rows)
+        .flat_map(|ty| (0..columns).map(move |tx| (tx, ty)))
+        .collect();
+
+    if order == TileOrder::Spiral {
+        // Sort by the square ring around the center tile, then by angle within the ring
+        let cx = columns.saturating_sub(1) as f64 / 2.0;
+        let cy = rows.saturating_sub(1) as f64 / 2.0;
+        let key = |&(tx, ty): &(u32, u32)| {
+            let (dx, dy) = (tx as f64 - cx, ty as f64 - cy);
+            (dx.abs().max(dy.abs()), dy.atan2(dx))
+        };
+        grid.sort_by(|a, b| {
+            let (ka, kb) = (key(a), key(b));
+            ka.0.total_cmp(&kb.0).then(ka.1.total_cmp(&kb.1))
+        });
+    }
+
+    grid.into_iter()
+        .map(|(tx, ty)| {
+            let (x, y) = (tx * tile_size, ty * tile_size);
+            Tile {
+                x,
+                y,
+                width: tile_size.min(width - x),
+                height: tile_size.min(height - y),
+            }
+        })
+        .collect()
+}
+
+/// Splits the row-major `pixels` of an image into the rows of each tile, so that every tile
+/// can be written by a different thread. The result is in the same order as `tiles`, which
+/// must come from `tiles` with the same `width` and `tile_size`.
+pub fn split_pixels<'a, T>(
+    pixels: &'a mut [T],
+    width: u32,
+    tile_size: u32,
+    tiles: &[Tile],
+) -> Vec<Vec<&'a mut [T]>> {
+    let columns = width.div_ceil(tile_size) as usize;
+    let grid_index = |x: u32, y: u32| (y / tile_size) as usize * columns + (x / tile_size) as usize;
+
+    let mut grid: Vec<Vec<&mut [T]>> = Vec::new();
+    grid.resize_with(tiles.len(), Vec::new);
+    for (y, row) in pixels.chunks_mut(width as usize).enumerate() {
+        for (tx, segment) in row.chunks_mut(tile_size as usize).enumerate() {
+            grid[grid_index(tx as u32 * tile_size, y as u32)].push(segment);
+        }
+    }
+
+    tiles
+        .iter()
+        .map(|tile| std::mem::take(&mut grid[grid_index(tile.x, tile.y)]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::Color;
+
+    #[test]
+    fn test_tiles_cover_image() {
+        for order in [TileOrder::Scanline, TileOrder::Spiral] {
+            let (width, height) = (37, 21);
+            let tiles = tiles(width, height, 8, order);
+            assert_eq!(tiles.len(), 5 * 3);
+
+            let mut pixels = vec![Color::zero(); (width * height) as usize];
+            for (tile, rows) in tiles
+                .iter()
+                .zip(split_pixels(&mut pixels, width, 8, &tiles))
+            {
+                assert_eq!(rows.len(), tile.height as usize);
+                for (dy, row) in rows.into_iter().enumerate() {
+                    assert_eq!(row.len(), tile.width as usize);
+                    for (dx, pixel) in row.iter_mut().enumerate() {
+                        let (x, y) = (tile.x + dx as u32, tile.y + dy as u32);
+                        *pixel += Color::new(x as f64, y as f64, 1.0);
+                    }
+                }
+            }
+
+            for (n, pixel) in pixels.iter().enumerate() {
+                let (x, y) = (n as u32 % width, n as u32 / width);
+                assert_eq!(pixel.as_tuple(), (x as f64, y as f64, 1.0));
+            }
+        }
+    }
+
+    #[test]
+    fn test_spiral_starts_at_center() {
+        let tiles = tiles(48, 48, 16, TileOrder::Spiral);
+        assert_eq!((tiles[0].x, tiles[0].y), (16, 16));
+    }
+}
@@ -0,0 +1,358 @@
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable, SharedHittable};
+use crate::matrix::Mat4;
+use crate::ray::Ray;
+use crate::util::{Point3, Time};
+use crate::vec3::{cross, dot, Vec3};
+use std::sync::Arc;
+
+/// Returns the axis-aligned box enclosing the eight transformed corners of `bbox`.
+fn transformed_box(bbox: &AABB, f: impl Fn(&Point3) -> Point3) -> AABB {
+    let mut lower = Point3::full(f64::INFINITY);
+    let mut upper = Point3::full(f64::NEG_INFINITY);
+
+    for i in 0..8 {
+        let corner = Point3::new(
+            if i & 1 == 0 { bbox.min.x } else { bbox.max.x },
+            if i & 2 == 0 { bbox.min.y } else { bbox.max.y },
+            if i & 4 == 0 { bbox.min.z } else { bbox.max.z },
+        );
+        let p = f(&corner);
+        for a in 0..3 {
+            lower[a] = lower[a].min(p[a]);
+            upper[a] = upper[a].max(p[a]);
+        }
+    }
+
+    AABB::new(lower, upper)
+}
+
+pub struct Translate {
+    object: SharedHittable,
+    offset: Vec3,
+}
+
+impl Translate {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(object: SharedHittable, offset: Vec3) -> SharedHittable {
+        Box::new(Translate { object, offset })
+    }
+}
+
+impl Hittable for Translate {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let moved = Ray {
+            origin: r.origin - self.offset,
+            ..*r
+        };
+        let mut rec = self.object.hit(&moved, t_min, t_max)?;
+        rec.p += self.offset;
+        Some(rec)
+    }
+
+    fn bounding_box(&self, t0: Time, t1: Time) -> Option<AABB> {
+        let bbox = self.object.bounding_box(t0, t1)?;
+        Some(AABB::new(bbox.min + self.offset, bbox.max + self.offset))
+    }
+
+    fn is_light(&self) -> bool {
+        self.object.is_light()
+    }
+
+    fn sample_direction(&self, origin: &Point3, u: (f64, f64)) -> Vec3 {
+        self.object.sample_direction(&(*origin - self.offset), u)
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.object.pdf_value(&(*origin - self.offset), direction)
+    }
+}
+
+pub struct RotateY {
+    object: SharedHittable,
+    sin_theta: f64,
+    cos_theta: f64,
+}
+
+impl RotateY {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(object: SharedHittable, degrees: f64) -> SharedHittable {
+        let (sin_theta, cos_theta) = degrees.to_radians().sin_cos();
+        Box::new(RotateY {
+            object,
+            sin_theta,
+            cos_theta,
+        })
+    }
+
+    fn to_object(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v.x - self.sin_theta * v.z,
+            v.y,
+            self.sin_theta * v.x + self.cos_theta * v.z,
+        )
+    }
+
+    fn to_world(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v.x + self.sin_theta * v.z,
+            v.y,
+            -self.sin_theta * v.x + self.cos_theta * v.z,
+        )
+    }
+}
+
+impl Hittable for RotateY {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let rotated = Ray {
+            origin: self.to_object(&r.origin),
+            direction: self.to_object(&r.direction),
+            ..*r
+        };
+        let mut rec = self.object.hit(&rotated, t_min, t_max)?;
+        rec.p = self.to_world(&rec.p);
+        rec.normal = self.to_world(&rec.normal);
+        Some(rec)
+    }
+
+    fn bounding_box(&self, t0: Time, t1: Time) -> Option<AABB> {
+        let bbox = self.object.bounding_box(t0, t1)?;
+        Some(transformed_box(&bbox, |p| self.to_world(p)))
+    }
+
+    fn is_light(&self) -> bool {
+        self.object.is_light()
+    }
+
+    // Rotations preserve solid angle, so densities carry over unchanged
+    fn sample_direction(&self, origin: &Point3, u: (f64, f64)) -> Vec3 {
+        let local = self.object.sample_direction(&self.to_object(origin), u);
+        self.to_world(&local)
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.object
+            .pdf_value(&self.to_object(origin), &self.to_object(direction))
+    }
+}
+
+/// Places an object in the world with an arbitrary affine transform.
+///
+/// Rays are moved into object space with the cached inverse, so the hit distance `t` is the
+/// same in both spaces; normals are brought back with the inverse transpose.
+pub struct Transform {
+    object: SharedHittable,
+    to_world: Mat4,
+    to_object: Mat4,
+    normal_to_world: Mat4,
+    /// Absolute determinant of the linear part of `to_object`
+    object_volume_scale: f64,
+}
+
+impl Transform {
+    /// Panics if `to_world` is not invertible.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(object: SharedHittable, to_world: Mat4) -> SharedHittable {
+        let to_object = to_world
+            .inverse()
+            .expect("Transform matrix must be invertible");
+        let [x, y, z] = [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        ]
+        .map(|axis| to_object.transform_vector(&axis));
+        Box::new(Transform {
+            object,
+            to_world,
+            to_object,
+            normal_to_world: to_object.transpose(),
+            object_volume_scale: dot(&x, &cross(&y, &z)).abs(),
+        })
+    }
+
+    /// Creates a transformed instance of geometry that is shared with other instances.
+    pub fn instance(object: &Arc<dyn Hittable + Send + Sync>, to_world: Mat4) -> SharedHittable {
+        Self::new(Box::new(object.clone()), to_world)
+    }
+}
+
+impl Hittable for Transform {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let local = Ray {
+            origin: self.to_object.transform_point(&r.origin),
+            direction: self.to_object.transform_vector(&r.direction),
+            ..*r
+        };
+        let mut rec = self.object.hit(&local, t_min, t_max)?;
+        rec.p = self.to_world.transform_point(&rec.p);
+        rec.normal = self
+            .normal_to_world
+            .transform_vector(&rec.normal)
+            .normalized();
+        Some(rec)
+    }
+
+    fn bounding_box(&self, t0: Time, t1: Time) -> Option<AABB> {
+        let bbox = self.object.bounding_box(t0, t1)?;
+        Some(transformed_box(&bbox, |p| self.to_world.transform_point(p)))
+    }
+
+    fn is_light(&self) -> bool {
+        self.object.is_light()
+    }
+
+    fn sample_direction(&self, origin: &Point3, u: (f64, f64)) -> Vec3 {
+        let local = self
+            .object
+            .sample_direction(&self.to_object.transform_point(origin), u);
+        self.to_world.transform_vector(&local)
+    }
+
+    /// The object-space density, scaled by how the transform stretches solid angle around
+    /// `direction`: a unit direction `w` maps to `v = M⁻¹w`, and `dω_object/dω_world` is
+    /// `|det M⁻¹| / |v|³`.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let local = self.to_object.transform_vector(&direction.normalized());
+        let length = local.mag();
+        let pdf = self
+            .object
+            .pdf_value(&self.to_object.transform_point(origin), &(local / length));
+        pdf * self.object_volume_scale / length.powi(3)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aarect::Rect2D;
+    use crate::cube::Cube;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColor;
+    use crate::util::Color;
+    use std::f64::consts::SQRT_2;
+
+    fn unit_sphere() -> SharedHittable {
+        let material = Lambertian::new(SolidColor::new(Color::one()));
+        Sphere::new(Point3::zero(), 1.0, material)
+    }
+
+    fn cube() -> SharedHittable {
+        let material = Lambertian::new(SolidColor::new(Color::one()));
+        Cube::new(Point3::full(-1.0), Point3::full(1.0), material)
+    }
+
+    fn corners(bbox: &AABB) -> Vec<Point3> {
+        (0..8)
+            .map(|i| {
+                Point3::new(
+                    if i & 1 == 0 { bbox.min.x } else { bbox.max.x },
+                    if i & 2 == 0 { bbox.min.y } else { bbox.max.y },
+                    if i & 4 == 0 { bbox.min.z } else { bbox.max.z },
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_translate() {
+        let moved = Translate::new(unit_sphere(), Vec3::new(2.0, 0.0, 0.0));
+        let r = Ray::new(Point3::new(2.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let rec = moved.hit(&r, 0.0, f64::INFINITY).unwrap();
+        assert!((rec.t - 4.0).abs() < 1e-12);
+        assert!((rec.p - Point3::new(2.0, 0.0, 1.0)).mag() < 1e-12);
+        assert!((rec.normal - Vec3::new(0.0, 0.0, 1.0)).mag() < 1e-12 && rec.front_face);
+
+        // From inside, the back face faces the ray
+        let inside = Ray::new(Point3::new(2.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        let rec = moved.hit(&inside, 0.0, f64::INFINITY).unwrap();
+        assert!((rec.p - Point3::new(3.0, 0.0, 0.0)).mag() < 1e-12);
+        assert!((rec.normal - Vec3::new(-1.0, 0.0, 0.0)).mag() < 1e-12 && !rec.front_face);
+
+        assert!(moved.hit(&r, 0.0, 3.5).is_none());
+        let bbox = moved.bounding_box(0.0, 1.0).unwrap();
+        assert_eq!(
+            (bbox.min.as_tuple(), bbox.max.as_tuple()),
+            ((1.0, -1.0, -1.0), (3.0, 1.0, 1.0))
+        );
+    }
+
+    #[test]
+    fn test_rotate_y() {
+        // A square facing +z at z = 1, turned to face +x at x = 1
+        let material = Lambertian::new(SolidColor::new(Color::one()));
+        let square = Rect2D::new_xy(-1.0, 1.0, -1.0, 1.0, 1.0, material);
+        let turned = RotateY::new(square, 90.0);
+        let r = Ray::new(Point3::new(5.0, 0.2, 0.3), Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        let rec = turned.hit(&r, 0.0, f64::INFINITY).unwrap();
+        assert!((rec.t - 4.0).abs() < 1e-12);
+        assert!((rec.p - Point3::new(1.0, 0.2, 0.3)).mag() < 1e-12);
+        assert!((rec.normal - Vec3::new(1.0, 0.0, 0.0)).mag() < 1e-12 && rec.front_face);
+        assert!((rec.u - 0.35).abs() < 1e-12 && (rec.v - 0.6).abs() < 1e-12);
+
+        let back = Ray::new(Point3::new(-5.0, 0.2, 0.3), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        let rec = turned.hit(&back, 0.0, f64::INFINITY).unwrap();
+        assert!((rec.normal - Vec3::new(-1.0, 0.0, 0.0)).mag() < 1e-12 && !rec.front_face);
+
+        // Turned by 45 degrees, the cube's corners reach out to sqrt(2) in x and z
+        let bbox = RotateY::new(cube(), 45.0).bounding_box(0.0, 1.0).unwrap();
+        assert!((bbox.max - Point3::new(SQRT_2, 1.0, SQRT_2)).mag() < 1e-12);
+        assert!((bbox.min + Point3::new(SQRT_2, 1.0, SQRT_2)).mag() < 1e-12);
+    }
+
+    #[test]
+    fn test_transform_non_uniform_scale() {
+        let to_world = Mat4::scale(&Vec3::new(2.0, 1.0, 1.0));
+        let ellipsoid = Transform::new(unit_sphere(), to_world);
+
+        // The hit distance is the same in object and world space
+        let r = Ray::new(Point3::new(5.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        let rec = ellipsoid.hit(&r, 0.0, f64::INFINITY).unwrap();
+        let local = Ray::new(Point3::new(2.5, 0.0, 0.0), Vec3::new(-0.5, 0.0, 0.0), 0.0);
+        let untransformed = unit_sphere().hit(&local, 0.0, f64::INFINITY).unwrap();
+        assert!((rec.t - 3.0).abs() < 1e-12 && (rec.t - untransformed.t).abs() < 1e-12);
+        assert!((rec.p - Point3::new(2.0, 0.0, 0.0)).mag() < 1e-12);
+        assert!((rec.normal - Vec3::new(1.0, 0.0, 0.0)).mag() < 1e-12 && rec.front_face);
+
+        // Off the axes the normal is the ellipsoid's, not the stretched sphere normal
+        let x = SQRT_2;
+        let y = 0.5f64.sqrt();
+        let down = Ray::new(Point3::new(x, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let rec = ellipsoid.hit(&down, 0.0, f64::INFINITY).unwrap();
+        assert!((rec.t - (5.0 - y)).abs() < 1e-9);
+        let expected = Vec3::new(x / 4.0, y, 0.0).normalized();
+        assert!((rec.normal - expected).mag() < 1e-9 && rec.front_face);
+
+        let inside = Ray::new(Point3::zero(), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        let rec = ellipsoid.hit(&inside, 0.0, f64::INFINITY).unwrap();
+        assert!((rec.t - 2.0).abs() < 1e-12 && !rec.front_face);
+        assert!((rec.normal - Vec3::new(-1.0, 0.0, 0.0)).mag() < 1e-12);
+    }
+
+    #[test]
+    fn test_transform_bounding_box() {
+        let to_world = Mat4::translate(&Vec3::new(1.0, 2.0, 3.0))
+            * Mat4::rotate(&Vec3::new(1.0, 1.0, 0.0), 30.0)
+            * Mat4::scale(&Vec3::new(2.0, 0.5, 1.0));
+        let object_box = cube().bounding_box(0.0, 1.0).unwrap();
+        let bbox = Transform::new(cube(), to_world)
+            .bounding_box(0.0, 1.0)
+            .unwrap();
+
+        // Every transformed corner is inside, and each face of the box touches one
+        let moved: Vec<Point3> = corners(&object_box)
+            .iter()
+            .map(|p| to_world.transform_point(p))
+            .collect();
+        for p in &moved {
+            for a in 0..3 {
+                assert!(bbox.min[a] - 1e-12 <= p[a] && p[a] <= bbox.max[a] + 1e-12);
+            }
+        }
+        for a in 0..3 {
+            assert!(moved.iter().any(|p| (p[a] - bbox.min[a]).abs() < 1e-12));
+            assert!(moved.iter().any(|p| (p[a] - bbox.max[a]).abs() < 1e-12));
+        }
+    }
+}
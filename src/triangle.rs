@@ -0,0 +1,223 @@
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable, SharedHittable};
+use crate::material::SharedMaterial;
+use crate::ray::Ray;
+use crate::util::{Point3, Time};
+use crate::vec3::*;
+
+pub struct Triangle {
+    vertices: [Point3; 3],
+    normals: Option<[Vec3; 3]>,
+    uvs: [(f64, f64); 3],
+    material: SharedMaterial,
+}
+
+impl Triangle {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(v0: Point3, v1: Point3, v2: Point3, material: SharedMaterial) -> SharedHittable {
+        Self::with_attributes(
+            [v0, v1, v2],
+            None,
+            [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)],
+            material,
+        )
+    }
+
+    /// Creates a triangle with per-vertex texture coordinates and, optionally, per-vertex
+    /// normals that are interpolated for smooth shading.
+    pub fn with_attributes(
+        vertices: [Point3; 3],
+        normals: Option<[Vec3; 3]>,
+        uvs: [(f64, f64); 3],
+        material: SharedMaterial,
+    ) -> SharedHittable {
+        Box::new(Triangle {
+            vertices,
+            normals,
+            uvs,
+            material,
+        })
+    }
+}
+
+/// Möller–Trumbore ray/triangle intersection, returning `(t, b1, b2)` where `b1` and `b2` are
+/// the barycentric weights of the second and third vertex.
+pub(crate) fn intersect(
+    vertices: &[Point3; 3],
+    r: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<(f64, f64, f64)> {
+    let edge1 = vertices[1] - vertices[0];
+    let edge2 = vertices[2] - vertices[0];
+
+    let pvec = cross(&r.direction, &edge2);
+    let det = dot(&edge1, &pvec);
+    // Ray is parallel to the triangle's plane
+    if det.abs() < 1.0e-12 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+
+    let tvec = r.origin - vertices[0];
+    let b1 = dot(&tvec, &pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+
+    let qvec = cross(&tvec, &edge1);
+    let b2 = dot(&r.direction, &qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = dot(&edge2, &qvec) * inv_det;
+    if t < t_min || t > t_max {
+        return None;
+    }
+
+    Some((t, b1, b2))
+}
+
+/// Builds the hit record for a triangle hit, interpolating the texture coordinates and, if
+/// present, the vertex normals with the barycentric weights from [`intersect`].
+pub(crate) fn hit_record(
+    vertices: &[Point3; 3],
+    normals: Option<&[Vec3; 3]>,
+    uvs: &[(f64, f64); 3],
+    r: &Ray,
+    (t, b1, b2): (f64, f64, f64),
+    material: SharedMaterial,
+) -> HitRecord {
+    let b0 = 1.0 - b1 - b2;
+    let u = b0 * uvs[0].0 + b1 * uvs[1].0 + b2 * uvs[2].0;
+    let v = b0 * uvs[0].1 + b1 * uvs[1].1 + b2 * uvs[2].1;
+
+    let geometric_normal =
+        cross(&(vertices[1] - vertices[0]), &(vertices[2] - vertices[0])).normalized();
+    let outward_normal = match normals {
+        Some(n) => {
+            let shading_normal = (b0 * n[0] + b1 * n[1] + b2 * n[2]).normalized();
+            // Keep the shading normal on the same side as the winding order
+            if dot(&shading_normal, &geometric_normal) < 0.0 {
+                -shading_normal
+            } else {
+                shading_normal
+            }
+        }
+        None => geometric_normal,
+    };
+
+    HitRecord::new(r.at(t), t, u, v, r, &outward_normal, material)
+}
+
+pub(crate) fn bounding_box(vertices: &[Point3; 3]) -> AABB {
+    let mut lower = vertices[0];
+    let mut upper = vertices[0];
+    for v in &vertices[1..] {
+        for a in 0..3 {
+            lower[a] = lower[a].min(v[a]);
+            upper[a] = upper[a].max(v[a]);
+        }
+    }
+
+    // Pad so axis-aligned triangles do not get a zero-width box
+    for a in 0..3 {
+        if upper[a] - lower[a] < 0.0001 {
+            lower[a] -= 0.0001;
+            upper[a] += 0.0001;
+        }
+    }
+    AABB::new(lower, upper)
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let hit = intersect(&self.vertices, r, t_min, t_max)?;
+        Some(hit_record(
+            &self.vertices,
+            self.normals.as_ref(),
+            &self.uvs,
+            r,
+            hit,
+            self.material.clone(),
+        ))
+    }
+
+    fn bounding_box(&self, _t0: Time, _t1: Time) -> Option<AABB> {
+        Some(bounding_box(&self.vertices))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::texture::SolidColor;
+    use crate::util::Color;
+
+    fn vertices() -> [Point3; 3] {
+        [
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+        ]
+    }
+
+    fn down_at(x: f64, y: f64) -> Ray {
+        Ray::new(Point3::new(x, y, 2.0), Vec3::new(0.0, 0.0, -1.0), 0.0)
+    }
+
+    #[test]
+    fn test_intersect() {
+        let (t, b1, b2) = intersect(&vertices(), &down_at(0.25, 0.5), 0.0, f64::INFINITY).unwrap();
+        assert!((t - 2.0).abs() < 1e-12 && (b1 - 0.25).abs() < 1e-12 && (b2 - 0.5).abs() < 1e-12);
+
+        // Outside the edges, beyond t_max and parallel to the plane
+        assert!(intersect(&vertices(), &down_at(0.75, 0.5), 0.0, f64::INFINITY).is_none());
+        assert!(intersect(&vertices(), &down_at(-0.1, 0.5), 0.0, f64::INFINITY).is_none());
+        assert!(intersect(&vertices(), &down_at(0.25, 0.5), 0.0, 1.5).is_none());
+        let parallel = Ray::new(Point3::new(-1.0, 0.2, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        assert!(intersect(&vertices(), &parallel, 0.0, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn test_interpolated_attributes() {
+        let material = Lambertian::new(SolidColor::new(Color::one()));
+        // Vertex normals tilted towards +x, wound so the geometric normal is +z
+        let normals = [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 1.0).normalized(),
+            Vec3::new(0.0, 0.0, 1.0),
+        ];
+        let triangle = Triangle::with_attributes(
+            vertices(),
+            Some(normals),
+            [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)],
+            material,
+        );
+        let rec = triangle
+            .hit(&down_at(0.25, 0.5), 0.0, f64::INFINITY)
+            .unwrap();
+        assert!((rec.u - 0.25).abs() < 1e-12 && (rec.v - 0.5).abs() < 1e-12);
+        assert!(rec.front_face);
+        let expected = (0.75 * Vec3::new(0.0, 0.0, 1.0) + 0.25 * normals[1]).normalized();
+        assert!((rec.normal - expected).mag() < 1e-12);
+
+        // From behind, the normal faces the ray
+        let up = Ray::new(Point3::new(0.25, 0.5, -2.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let rec = triangle.hit(&up, 0.0, f64::INFINITY).unwrap();
+        assert!(!rec.front_face && rec.normal.z < 0.0);
+    }
+
+    #[test]
+    fn test_bounding_box_is_padded() {
+        let bbox = bounding_box(&vertices());
+        assert_eq!(
+            (bbox.min.x, bbox.min.y, bbox.max.x, bbox.max.y),
+            (0.0, 0.0, 1.0, 1.0)
+        );
+        assert!(bbox.min.z < 0.0 && bbox.max.z > 0.0);
+        assert!(bbox.hit(&down_at(0.25, 0.5), 0.0, f64::INFINITY));
+    }
+}
@@ -1,4 +1,5 @@
-use rand::prelude::*;
+use rand::{Rng as _, SeedableRng};
+use rand_pcg::Pcg32;
 
 use crate::vec3::Vec3;
 
@@ -6,10 +7,28 @@ pub type Point3 = Vec3;
 pub type Color = Vec3;
 pub type Time = f64;
 
-pub fn rand() -> f64 {
-    thread_rng().gen()
+/// Random number generator used for rendering and scene generation. It is cheap to seed, so
+/// every camera sample gets a fresh one.
+pub type Rng = Pcg32;
+
+/// Scrambles the bits of `x` (the SplitMix64 finalizer).
+pub fn hash_u64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Returns the generator for sample `sample` of pixel `pixel`. Every sample has its own
+/// stream, so an image is the same no matter how its pixels are spread over threads.
+pub fn sample_rng(seed: u64, pixel: u64, sample: u64) -> Rng {
+    Rng::seed_from_u64(hash_u64(hash_u64(hash_u64(seed) ^ pixel) ^ sample))
+}
+
+pub fn rand(rng: &mut Rng) -> f64 {
+    rng.gen()
 }
 
-pub fn rand_range(min: f64, max: f64) -> f64 {
-    thread_rng().gen_range(min..max)
+pub fn rand_range(min: f64, max: f64, rng: &mut Rng) -> f64 {
+    rng.gen_range(min..max)
 }
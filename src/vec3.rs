@@ -2,6 +2,7 @@ use std::ops;
 use std::ops::{Add, Div, Mul, Neg, Sub};
 
 use crate::util::*;
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
 
 #[derive(Debug, Copy, Clone)]
 pub struct Vec3 {
@@ -184,15 +185,15 @@ impl Vec3 {
         Self::new(a, a, a)
     }
 
-    pub fn random() -> Vec3 {
-        Self::new(rand(), rand(), rand())
+    pub fn random(rng: &mut Rng) -> Vec3 {
+        Self::new(rand(rng), rand(rng), rand(rng))
     }
 
-    pub fn random_range(min: f64, max: f64) -> Vec3 {
+    pub fn random_range(min: f64, max: f64, rng: &mut Rng) -> Vec3 {
         Self::new(
-            rand_range(min, max),
-            rand_range(min, max),
-            rand_range(min, max),
+            rand_range(min, max, rng),
+            rand_range(min, max, rng),
+            rand_range(min, max, rng),
         )
     }
 
@@ -228,6 +229,10 @@ impl Vec3 {
         self.x * self.y * self.z
     }
 
+    pub fn max_element(&self) -> f64 {
+        self.x.max(self.y).max(self.z)
+    }
+
     pub fn near_zero(&self) -> bool {
         let eps = 1.0e-8;
         self.x.abs() < eps && self.y.abs() < eps && self.z.abs() < eps
@@ -237,6 +242,14 @@ impl Vec3 {
         Vec3::new(self.x.sqrt(), self.y.sqrt(), self.z.sqrt())
     }
 
+    pub fn exp(&self) -> Vec3 {
+        Vec3::new(self.x.exp(), self.y.exp(), self.z.exp())
+    }
+
+    pub fn ln(&self) -> Vec3 {
+        Vec3::new(self.x.ln(), self.y.ln(), self.z.ln())
+    }
+
     pub fn clamp(&self, min: f64, max: f64) -> Vec3 {
         Vec3::new(
             self.x.clamp(min, max),
@@ -246,17 +259,30 @@ impl Vec3 {
     }
 }
 
-pub fn random_in_unit_sphere() -> Vec3 {
+pub fn random_in_unit_sphere(rng: &mut Rng) -> Vec3 {
     loop {
-        let v = Vec3::random_range(-1.0, 1.0);
+        let v = Vec3::random_range(-1.0, 1.0, rng);
         if v.mag_squared() < 1.0 {
             return v;
         }
     }
 }
 
-pub fn random_unit_vector() -> Vec3 {
-    random_in_unit_sphere().normalized()
+pub fn random_unit_vector(rng: &mut Rng) -> Vec3 {
+    random_in_unit_sphere(rng).normalized()
+}
+
+/// Maps a uniform 2D sample to a uniformly distributed unit vector.
+pub fn sample_unit_vector((u1, u2): (f64, f64)) -> Vec3 {
+    let z = 1.0 - 2.0 * u1;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Maps uniform samples to a uniformly distributed point in the unit sphere.
+pub fn sample_in_unit_sphere(u: (f64, f64), u_radius: f64) -> Vec3 {
+    u_radius.cbrt() * sample_unit_vector(u)
 }
 
 pub fn dot(v: &Vec3, u: &Vec3) -> f64 {
@@ -282,15 +308,42 @@ pub fn cross(u: &Vec3, v: &Vec3) -> Vec3 {
     )
 }
 
-pub fn random_in_unit_disk() -> Vec3 {
+/// Returns two unit vectors that form an orthonormal basis with the unit vector `n` (Duff et
+/// al., "Building an Orthonormal Basis, Revisited").
+pub fn orthonormal_basis(n: &Vec3) -> (Vec3, Vec3) {
+    let sign = 1.0f64.copysign(n.z);
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+    (
+        Vec3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+        Vec3::new(b, sign + n.y * n.y * a, -n.y),
+    )
+}
+
+pub fn random_in_unit_disk(rng: &mut Rng) -> Vec3 {
     loop {
-        let p = Vec3::new(rand_range(-1.0, 1.0), rand_range(-1.0, 1.0), 0.0);
+        let p = Vec3::new(rand_range(-1.0, 1.0, rng), rand_range(-1.0, 1.0, rng), 0.0);
         if p.mag_squared() < 1.0 {
             return p;
         }
     }
 }
 
+/// Maps a uniform 2D sample to a uniformly distributed point in the unit disk, keeping
+/// nearby samples nearby (Shirley and Chiu's concentric mapping).
+pub fn sample_in_unit_disk((u1, u2): (f64, f64)) -> Vec3 {
+    let (a, b) = (2.0 * u1 - 1.0, 2.0 * u2 - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return Vec3::zero();
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, FRAC_PI_4 * (b / a))
+    } else {
+        (b, FRAC_PI_2 - FRAC_PI_4 * (a / b))
+    };
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -349,4 +402,18 @@ mod tests {
         let mag = v.mag();
         assert!(fapprox_eq(mag * mag, 50.0))
     }
+
+    #[test]
+    fn test_orthonormal_basis() {
+        for n in [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(1.0, -2.0, 0.5).normalized(),
+        ] {
+            let (s, t) = orthonormal_basis(&n);
+            assert!(fapprox_eq(s.mag(), 1.0) && fapprox_eq(t.mag(), 1.0));
+            assert!(fapprox_eq(dot(&s, &t), 0.0) && fapprox_eq(dot(&s, &n), 0.0));
+            assert!(fapprox_eq(dot(&t, &n), 0.0));
+        }
+    }
 }